@@ -0,0 +1,85 @@
+use crate::ray::Ray;
+use crate::vec3::*;
+
+/// Axis aligned bounding box.
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    /// The box spanned by two opposite corners, in any order.
+    pub fn new(a: Point3, b: Point3) -> Self {
+        Aabb {
+            min: Vec3(a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            max: Vec3(a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        }
+    }
+
+    /// The box around a point with half its size along each axis being `extent`.
+    pub fn around(center: Point3, extent: Vec3) -> Self {
+        Aabb::new(center - extent, center + extent)
+    }
+
+    /// The smallest box holding both boxes.
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3(
+                self.min.0.min(other.min.0),
+                self.min.1.min(other.min.1),
+                self.min.2.min(other.min.2),
+            ),
+            max: Vec3(
+                self.max.0.max(other.max.0),
+                self.max.1.max(other.max.1),
+                self.max.2.max(other.max.2),
+            ),
+        }
+    }
+
+    /// The eight corners of the box.
+    pub fn corners(&self) -> [Point3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vec3(a.0, a.1, a.2),
+            Vec3(b.0, a.1, a.2),
+            Vec3(a.0, b.1, a.2),
+            Vec3(b.0, b.1, a.2),
+            Vec3(a.0, a.1, b.2),
+            Vec3(b.0, a.1, b.2),
+            Vec3(a.0, b.1, b.2),
+            Vec3(b.0, b.1, b.2),
+        ]
+    }
+
+    /// The part of `[t_min, t_max]` the ray spends inside the box, by the
+    /// slab method. `None` when it misses.
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (mut t0, mut t1) = (t_min, t_max);
+        let slabs = [
+            (r.orig.0, r.dir.0, self.min.0, self.max.0),
+            (r.orig.1, r.dir.1, self.min.1, self.max.1),
+            (r.orig.2, r.dir.2, self.min.2, self.max.2),
+        ];
+        for &(orig, dir, min, max) in slabs.iter() {
+            let inv = 1.0 / dir;
+            let (mut near, mut far) = ((min - orig) * inv, (max - orig) * inv);
+            if inv < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // Rays inside a slab they're parallel to give NaN, which the
+            // comparisons leave out.
+            if near > t0 {
+                t0 = near;
+            }
+            if far < t1 {
+                t1 = far;
+            }
+            if t1 < t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+}
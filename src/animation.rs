@@ -0,0 +1,301 @@
+use crate::camera::CameraParams;
+use crate::hittable::{HittableList, Transformed};
+use crate::vec3::*;
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::{Add, Mul, Sub};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How a track gets from one keyframe to the next.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interpolation {
+    /// Holds the value until the next keyframe.
+    Step,
+    Linear,
+    /// A cubic Bezier curve with smooth, automatically placed handles that
+    /// ease in and out of the first and last keyframes.
+    Bezier,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "step" => Ok(Interpolation::Step),
+            "linear" => Ok(Interpolation::Linear),
+            "bezier" => Ok(Interpolation::Bezier),
+            _ => Err(format!("Unknown interpolation: {}", s)),
+        }
+    }
+}
+
+/// A value that can be keyframed.
+pub trait Animatable:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f64, Output = Self>
+{
+}
+
+impl Animatable for f64 {}
+impl Animatable for Vec3 {}
+
+#[derive(Clone, Copy)]
+pub struct Keyframe<T> {
+    pub frame: f64,
+    pub value: T,
+    /// How to get from this keyframe to the next one.
+    pub interpolation: Interpolation,
+}
+
+/// Keyframes of one value, sorted by frame.
+#[derive(Clone)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new() -> Self {
+        Track { keys: Vec::new() }
+    }
+
+    /// Adds a keyframe, replacing any already at the same frame.
+    pub fn insert(&mut self, key: Keyframe<T>) {
+        match self
+            .keys
+            .binary_search_by(|k| k.frame.total_cmp(&key.frame))
+        {
+            Ok(i) => self.keys[i] = key,
+            Err(i) => self.keys.insert(i, key),
+        }
+    }
+
+    /// The value at `frame`, held constant before the first keyframe and
+    /// after the last one. `None` for a track without keyframes.
+    pub fn sample(&self, frame: f64) -> Option<T> {
+        let keys = &self.keys;
+        let next = keys.partition_point(|k| k.frame <= frame);
+        if next == 0 {
+            return keys.first().map(|k| k.value);
+        }
+        if next == keys.len() {
+            return keys.last().map(|k| k.value);
+        }
+        let (k0, k1) = (&keys[next - 1], &keys[next]);
+        let span = k1.frame - k0.frame;
+        let t = (frame - k0.frame) / span;
+        match k0.interpolation {
+            Interpolation::Step => Some(k0.value),
+            Interpolation::Linear => Some(k0.value + (k1.value - k0.value) * t),
+            Interpolation::Bezier => {
+                // Handles a third of the way along the tangents, which are
+                // Catmull-Rom ones inside the track and flat at its ends.
+                let c1 = k0.value + self.slope(next - 1) * (span / 3.0);
+                let c2 = k1.value - self.slope(next) * (span / 3.0);
+                let s = 1.0 - t;
+                Some(
+                    k0.value * (s * s * s)
+                        + c1 * (3.0 * s * s * t)
+                        + c2 * (3.0 * s * t * t)
+                        + k1.value * (t * t * t),
+                )
+            }
+        }
+    }
+
+    /// Rate of change per frame at keyframe `i`.
+    fn slope(&self, i: usize) -> T {
+        let keys = &self.keys;
+        if i == 0 || i + 1 == keys.len() {
+            return keys[i].value * 0.0;
+        }
+        let (prev, next) = (&keys[i - 1], &keys[i + 1]);
+        (next.value - prev.value) * (1.0 / (next.frame - prev.frame))
+    }
+}
+
+impl<T: Animatable> Default for Track<T> {
+    fn default() -> Self {
+        Track::new()
+    }
+}
+
+/// Animated transform and tint of one object, see `Transformed`.
+#[derive(Clone, Default)]
+pub struct ObjectTracks {
+    pub translate: Track<Vec3>,
+    /// Euler angles in degrees.
+    pub rotate: Track<Vec3>,
+    pub scale: Track<f64>,
+    pub tint: Track<Vec3>,
+}
+
+/// Keyframed camera and objects, applied on top of a scene for each frame.
+#[derive(Clone, Default)]
+pub struct Animation {
+    pub lookfrom: Track<Vec3>,
+    pub lookat: Track<Vec3>,
+    /// Vertical field of view in degrees.
+    pub fov: Track<f64>,
+    /// Tracks of the objects, by their ID in the scene's `HittableList`.
+    pub objects: BTreeMap<u32, ObjectTracks>,
+}
+
+impl Animation {
+    /// Loads an animation file, see `parse`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Animation::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses keyframes, one per line:
+    ///
+    /// ```text
+    /// # target             frame  interpolation  value
+    /// camera.lookfrom      0      bezier         13 2 3
+    /// camera.fov           0      linear         20
+    /// object.12.rotate     24     linear         0 90 0
+    /// ```
+    ///
+    /// Camera targets are `lookfrom`, `lookat` and `fov`. Objects are picked
+    /// by their ID, as written to the object ID AOV, with `translate`,
+    /// `rotate` (degrees about X, Y and Z), `scale` and `tint` targets.
+    /// Interpolation is `step`, `linear` or `bezier`.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut animation = Animation::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            animation.parse_key(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Line {}: {}", number + 1, e),
+                )
+            })?;
+        }
+        Ok(animation)
+    }
+
+    fn parse_key(&mut self, line: &str) -> Result<(), String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err("expected a target, frame, interpolation and value".to_string());
+        }
+        let frame: f64 = fields[1]
+            .parse()
+            .map_err(|_| format!("invalid frame {}", fields[1]))?;
+        let interpolation: Interpolation = fields[2].parse()?;
+        let values = fields[3..]
+            .iter()
+            .map(|v| v.parse().map_err(|_| format!("invalid value {}", v)))
+            .collect::<Result<Vec<f64>, String>>()?;
+        let scalar = || match values.as_slice() {
+            &[x] => Ok(Keyframe {
+                frame,
+                value: x,
+                interpolation,
+            }),
+            _ => Err(format!("{} takes one value", fields[0])),
+        };
+        let vector = || match values.as_slice() {
+            &[x, y, z] => Ok(Keyframe {
+                frame,
+                value: Vec3(x, y, z),
+                interpolation,
+            }),
+            _ => Err(format!("{} takes three values", fields[0])),
+        };
+
+        let target: Vec<&str> = fields[0].split('.').collect();
+        match target.as_slice() {
+            ["camera", "lookfrom"] => self.lookfrom.insert(vector()?),
+            ["camera", "lookat"] => self.lookat.insert(vector()?),
+            ["camera", "fov"] => self.fov.insert(scalar()?),
+            ["object", id, property] => {
+                let id: u32 = id
+                    .parse()
+                    .map_err(|_| format!("invalid object ID {}", id))?;
+                let tracks = self.objects.entry(id).or_default();
+                match *property {
+                    "translate" => tracks.translate.insert(vector()?),
+                    "rotate" => tracks.rotate.insert(vector()?),
+                    "scale" => tracks.scale.insert(scalar()?),
+                    "tint" => tracks.tint.insert(vector()?),
+                    _ => return Err(format!("unknown object property {}", property)),
+                }
+            }
+            _ => return Err(format!("unknown target {}", fields[0])),
+        }
+        Ok(())
+    }
+
+    /// Poses the camera and objects as they are at `frame`. Untracked values
+    /// keep what the scene set up.
+    pub fn apply(&self, frame: f64, world: &mut HittableList, params: &mut CameraParams) {
+        if let Some(lookfrom) = self.lookfrom.sample(frame) {
+            params.lookfrom = lookfrom;
+        }
+        if let Some(lookat) = self.lookat.sample(frame) {
+            params.lookat = lookat;
+        }
+        if let Some(fov) = self.fov.sample(frame) {
+            params.vfov = fov;
+        }
+
+        for (&id, tracks) in &self.objects {
+            let object = match world.get(id) {
+                Some(object) => object.clone(),
+                None => {
+                    eprintln!("Animation refers to missing object {}", id);
+                    continue;
+                }
+            };
+            let transformed = Transformed::new(
+                &object,
+                tracks
+                    .translate
+                    .sample(frame)
+                    .unwrap_or(Vec3(0.0, 0.0, 0.0)),
+                tracks.rotate.sample(frame).unwrap_or(Vec3(0.0, 0.0, 0.0)),
+                tracks.scale.sample(frame).unwrap_or(1.0),
+                tracks.tint.sample(frame),
+            );
+            world.set(id, &transformed.into());
+        }
+    }
+}
+
+/// The output path of `frame`: a printf style `%d` in the pattern (like
+/// `%04d` for zero padding to four digits) is replaced by the frame number.
+/// Patterns without one get `_0000` style numbers before their extension.
+pub fn frame_path(pattern: &str, frame: u32) -> String {
+    if let Some(start) = pattern.find('%') {
+        let spec = &pattern[start + 1..];
+        if let Some(end) = spec.find('d') {
+            let flags = &spec[..end];
+            if flags.chars().all(|c| c.is_ascii_digit()) {
+                let width: usize = flags.parse().unwrap_or(0);
+                let number = if flags.starts_with('0') {
+                    format!("{:0width$}", frame, width = width)
+                } else {
+                    format!("{:width$}", frame, width = width)
+                };
+                return format!("{}{}{}", &pattern[..start], number, &spec[end + 1..]);
+            }
+        }
+    }
+    let path = Path::new(pattern);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => path
+            .with_file_name(format!(
+                "{}_{:04}.{}",
+                stem.to_string_lossy(),
+                frame,
+                ext.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{}_{:04}", pattern, frame),
+    }
+}
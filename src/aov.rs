@@ -0,0 +1,209 @@
+use crate::checkpoint::{read_f64, read_u32};
+use crate::color::Color;
+use crate::film::crop_buffer;
+use crate::hittable::HitRecord;
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Auxiliary values of one camera sample, taken at its first hit.
+pub struct AovSample {
+    /// Distance along the camera's viewing axis, zero for misses.
+    pub depth: f64,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub position: Vec3,
+    /// Zero for misses.
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl AovSample {
+    /// The sample of a ray that escaped, whose albedo is the background.
+    pub fn miss(background: Color) -> Self {
+        AovSample {
+            depth: 0.0,
+            normal: Vec3(0.0, 0.0, 0.0),
+            albedo: background,
+            position: Vec3(0.0, 0.0, 0.0),
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+}
+
+/// Per-pixel auxiliary buffers for compositing and denoising. Continuous
+/// values are averaged over the pixel's samples, IDs come from its first one.
+#[derive(Clone)]
+pub struct AovBuffers {
+    pub width: i32,
+    pub height: i32,
+    count: Vec<u32>,
+    pub depth: Vec<f64>,
+    pub normal: Vec<Vec3>,
+    pub albedo: Vec<Color>,
+    pub position: Vec<Vec3>,
+    pub object_id: Vec<u32>,
+    pub material_id: Vec<u32>,
+    /// Material IDs handed out so far, in order of first appearance.
+    material_ids: HashMap<usize, u32>,
+    /// Highest material ID read back from a checkpoint, new ones come after.
+    resumed_ids: u32,
+}
+
+impl AovBuffers {
+    pub fn new(width: i32, height: i32) -> Self {
+        let n = (width * height) as usize;
+        let zero = Vec3(0.0, 0.0, 0.0);
+        AovBuffers {
+            width,
+            height,
+            count: vec![0; n],
+            depth: vec![0.0; n],
+            normal: vec![zero; n],
+            albedo: vec![zero; n],
+            position: vec![zero; n],
+            object_id: vec![0; n],
+            material_id: vec![0; n],
+            material_ids: HashMap::new(),
+            resumed_ids: 0,
+        }
+    }
+
+    /// The sample of a ray whose first hit is `rec`, at `depth` from the camera.
+    pub fn hit_sample(&mut self, rec: &HitRecord, depth: f64) -> AovSample {
+        let mat = rec.mat_ptr.as_ref().unwrap();
+        let key = Rc::as_ptr(mat) as *const () as usize;
+        let next_id = self.resumed_ids + self.material_ids.len() as u32 + 1;
+        AovSample {
+            depth,
+            normal: rec.normal,
+            albedo: mat.albedo(rec),
+            position: rec.p,
+            object_id: rec.object_id,
+            material_id: *self.material_ids.entry(key).or_insert(next_id),
+        }
+    }
+
+    pub fn add(&mut self, x: i32, y: i32, sample: AovSample) {
+        let i = (y * self.width + x) as usize;
+        self.count[i] += 1;
+        let w = 1.0 / self.count[i] as f64;
+        let blend = |avg: Vec3, v: Vec3| avg + w * (v - avg);
+        self.depth[i] += w * (sample.depth - self.depth[i]);
+        self.normal[i] = blend(self.normal[i], sample.normal);
+        self.albedo[i] = blend(self.albedo[i], sample.albedo);
+        self.position[i] = blend(self.position[i], sample.position);
+        if self.count[i] == 1 {
+            self.object_id[i] = sample.object_id;
+            self.material_id[i] = sample.material_id;
+        }
+    }
+
+    /// The buffers inside `columns` and `rows`, see `Film::crop`.
+    pub fn crop(&self, (columns, rows): (Range<i32>, Range<i32>)) -> Self {
+        let ranges = || (columns.clone(), rows.clone());
+        AovBuffers {
+            width: columns.len() as i32,
+            height: rows.len() as i32,
+            count: crop_buffer(&self.count, self.width, ranges()),
+            depth: crop_buffer(&self.depth, self.width, ranges()),
+            normal: crop_buffer(&self.normal, self.width, ranges()),
+            albedo: crop_buffer(&self.albedo, self.width, ranges()),
+            position: crop_buffer(&self.position, self.width, ranges()),
+            object_id: crop_buffer(&self.object_id, self.width, ranges()),
+            material_id: crop_buffer(&self.material_id, self.width, ranges()),
+            material_ids: self.material_ids.clone(),
+            resumed_ids: self.resumed_ids,
+        }
+    }
+
+    /// Writes the buffers losslessly, for checkpoints. Which material each
+    /// material ID stood for isn't kept: a resumed render numbers the
+    /// materials it meets after the highest saved ID.
+    pub fn write_raw(&self, out: &mut impl Write) -> io::Result<()> {
+        for i in 0..self.count.len() {
+            out.write_all(&self.count[i].to_le_bytes())?;
+            let (n, a, p) = (self.normal[i], self.albedo[i], self.position[i]);
+            let values = [self.depth[i], n.0, n.1, n.2, a.0, a.1, a.2, p.0, p.1, p.2];
+            for x in values.iter() {
+                out.write_all(&x.to_le_bytes())?;
+            }
+            out.write_all(&self.object_id[i].to_le_bytes())?;
+            out.write_all(&self.material_id[i].to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_raw(width: i32, height: i32, input: &mut impl Read) -> io::Result<Self> {
+        // Three u32s and ten f64s per pixel.
+        const PIXEL_BYTES: u64 = 3 * 4 + 10 * 8;
+        let mut aovs = AovBuffers::new(width, height);
+        let expected = aovs.count.len() as u64 * PIXEL_BYTES;
+        let mut payload = Vec::new();
+        input.take(expected).read_to_end(&mut payload)?;
+        if payload.len() as u64 != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Truncated AOV data",
+            ));
+        }
+        let input = &mut payload.as_slice();
+        let vec3 = |input: &mut &[u8]| -> io::Result<Vec3> {
+            Ok(Vec3(read_f64(input)?, read_f64(input)?, read_f64(input)?))
+        };
+        for i in 0..aovs.count.len() {
+            aovs.count[i] = read_u32(input)?;
+            aovs.depth[i] = read_f64(input)?;
+            aovs.normal[i] = vec3(input)?;
+            aovs.albedo[i] = vec3(input)?;
+            aovs.position[i] = vec3(input)?;
+            aovs.object_id[i] = read_u32(input)?;
+            aovs.material_id[i] = read_u32(input)?;
+        }
+        aovs.resumed_ids = aovs.material_id.iter().copied().max().unwrap_or(0);
+        Ok(aovs)
+    }
+
+    /// Saves every buffer as a PFM file named `<prefix>_<buffer>.pfm`.
+    pub fn save(&self, prefix: &str) -> io::Result<()> {
+        let ids = |ids: &[u32]| ids.iter().map(|&id| Vec3(id as f64, 0.0, 0.0)).collect();
+        let depth = self.depth.iter().map(|&d| Vec3(d, 0.0, 0.0)).collect();
+        let buffers: [(&str, usize, Vec<Vec3>); 6] = [
+            ("depth", 1, depth),
+            ("normal", 3, self.normal.clone()),
+            ("albedo", 3, self.albedo.clone()),
+            ("position", 3, self.position.clone()),
+            ("object_id", 1, ids(&self.object_id)),
+            ("material_id", 1, ids(&self.material_id)),
+        ];
+        for (name, channels, data) in buffers.iter() {
+            let path = format!("{}_{}.pfm", prefix, name);
+            write_pfm(&path, self.width, self.height, *channels, data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a little endian PFM image, grayscale from the first component when
+/// `channels` is 1. PFM stores the bottom row first, like the film.
+pub fn write_pfm(
+    path: impl AsRef<Path>,
+    width: i32,
+    height: i32,
+    channels: usize,
+    data: &[Vec3],
+) -> io::Result<()> {
+    let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+    let magic = if channels == 1 { "Pf" } else { "PF" };
+    write!(out, "{}\n{} {}\n-1.0\n", magic, width, height)?;
+    for v in data {
+        for &x in [v.0, v.1, v.2].iter().take(channels) {
+            out.write_all(&(x as f32).to_le_bytes())?;
+        }
+    }
+    out.flush()
+}
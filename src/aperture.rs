@@ -0,0 +1,152 @@
+use crate::camera::PI;
+use crate::film::luminance;
+use crate::texture::{ImageTexture, Texture};
+use crate::vec3::*;
+use std::rc::Rc;
+
+/// The shape of the lens opening, which is the shape out of focus
+/// highlights take.
+#[derive(Clone, Default)]
+pub enum ApertureShape {
+    #[default]
+    Circle,
+    /// A regular polygon with its corners on the unit circle, as formed by
+    /// `blades` straight diaphragm blades. `rotation` is in radians.
+    Polygon { blades: u32, rotation: f64 },
+    /// An arbitrary opening, see `ApertureMask`.
+    Mask(Rc<ApertureMask>),
+}
+
+/// A thin lens aperture: positions on the lens are sampled in the unit disk
+/// and scaled by the lens radius by the camera.
+#[derive(Clone, Default)]
+pub struct Aperture {
+    pub shape: ApertureShape,
+    /// Cat's eye vignetting: how far the lens barrel clips the opening at the
+    /// corners of the frame, in aperture radii. Zero turns it off.
+    pub cat_eye: f64,
+}
+
+impl Aperture {
+    pub fn new(shape: ApertureShape, cat_eye: f64) -> Self {
+        Aperture { shape, cat_eye }
+    }
+
+    /// Position on the lens for a sample of the unit square, seen from film
+    /// position `film`, which is (0, 0) in the middle of the frame and has
+    /// length one in its corners. `None` when the lens barrel blocks it.
+    pub fn sample(&self, lens: (f64, f64), film: (f64, f64)) -> Option<(f64, f64)> {
+        let (x, y) = match self.shape {
+            ApertureShape::Circle => {
+                let p = Vec3::concentric_disk(lens);
+                (p.x(), p.y())
+            }
+            ApertureShape::Polygon { blades, rotation } => {
+                sample_polygon(blades.max(3), rotation, lens)
+            }
+            ApertureShape::Mask(ref mask) => mask.sample(lens),
+        };
+
+        // The barrel is a second opening of the same size that slides
+        // outwards with the distance from the center of the frame.
+        let (bx, by) = (self.cat_eye * film.0, self.cat_eye * film.1);
+        if (x - bx) * (x - bx) + (y - by) * (y - by) > 1.0 {
+            return None;
+        }
+        Some((x, y))
+    }
+}
+
+/// Uniform sample of a regular polygon, picking one of the triangles fanning
+/// out from its center with the first coordinate.
+fn sample_polygon(blades: u32, rotation: f64, (u, v): (f64, f64)) -> (f64, f64) {
+    let n = blades as f64;
+    let scaled = u * n;
+    let index = scaled.floor().min(n - 1.0);
+    let u = scaled - index;
+
+    let corner = |k: f64| {
+        let angle = rotation + 2.0 * PI * k / n;
+        (angle.cos(), angle.sin())
+    };
+    let (a, b) = (corner(index), corner(index + 1.0));
+    let su = u.sqrt();
+    let (wa, wb) = (su * (1.0 - v), su * v);
+    (wa * a.0 + wb * b.0, wa * a.1 + wb * b.1)
+}
+
+/* ========================================== */
+/// An aperture drawn as a grayscale image, brighter pixels letting more light
+/// through. The image covers the square around the unit disk.
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    /// Cumulative brightness of the rows, from the bottom.
+    row_cdf: Vec<f64>,
+    /// Cumulative brightness of the pixels within each row.
+    pixel_cdfs: Vec<Vec<f64>>,
+}
+
+impl ApertureMask {
+    /// Builds the mask from an image, returning `None` when it's all black.
+    pub fn new(image: &ImageTexture) -> Option<Self> {
+        let (width, height) = image.size();
+        let origin = Vec3(0.0, 0.0, 0.0);
+        let pixel_cdfs: Vec<Vec<f64>> = (0..height)
+            .map(|j| {
+                let v = (j as f64 + 0.5) / height as f64;
+                cumulative((0..width).map(|i| {
+                    let u = (i as f64 + 0.5) / width as f64;
+                    luminance(image.value(u, v, &origin)).max(0.0)
+                }))
+            })
+            .collect();
+        let row_cdf = cumulative(pixel_cdfs.iter().map(|cdf| cdf[width]));
+        if row_cdf[height] <= 0.0 {
+            return None;
+        }
+        Some(ApertureMask {
+            width,
+            height,
+            row_cdf,
+            pixel_cdfs,
+        })
+    }
+
+    /// Position in [-1, 1]², sampled proportionally to the mask's brightness.
+    pub fn sample(&self, (u, v): (f64, f64)) -> (f64, f64) {
+        let (j, y) = sample_cdf(&self.row_cdf, v);
+        let (_, x) = sample_cdf(&self.pixel_cdfs[j], u);
+        (
+            2.0 * x / self.width as f64 - 1.0,
+            2.0 * y / self.height as f64 - 1.0,
+        )
+    }
+}
+
+/// Running sums of `weights`, starting at zero.
+fn cumulative(weights: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut cdf = vec![0.0];
+    for w in weights {
+        cdf.push(cdf[cdf.len() - 1] + w);
+    }
+    cdf
+}
+
+/// Inverts a cumulative sum for `u` in [0, 1), returning the cell it falls in
+/// and the continuous position, in cells, within the whole range.
+fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let target = u * cdf[cdf.len() - 1];
+    // The last cell whose start is at or below the target, skipping empty ones.
+    let cell = cdf
+        .partition_point(|&c| c <= target)
+        .clamp(1, cdf.len() - 1)
+        - 1;
+    let width = cdf[cell + 1] - cdf[cell];
+    let offset = if width > 0.0 {
+        (target - cdf[cell]) / width
+    } else {
+        0.5
+    };
+    (cell, cell as f64 + offset.min(1.0))
+}
@@ -1,14 +1,241 @@
+use crate::aperture::Aperture;
 use crate::vec3::*;
 use crate::*;
+use std::str::FromStr;
 
-pub const PI: f64 = std::f64::consts::PI;   
+pub const PI: f64 = std::f64::consts::PI;
 
 #[inline]
 pub fn deg_to_rad(deg: f64) -> f64 {
     deg * PI / 180.0
 }
 
-pub struct Camera {
+/// A camera turns film positions into rays.
+pub trait Camera {
+    /// The ray through film position (s, t), both in [0, 1] from the bottom
+    /// left corner, with the lens position taken from a sample of the unit
+    /// square. `None` when nothing is seen through that position, e.g.
+    /// outside a fisheye's image circle.
+    fn generate_ray(&self, s: f64, t: f64, lens: (f64, f64)) -> Option<Ray>;
+
+    /// The ray one color channel (0 red, 1 green, 2 blue) is seen along, for
+    /// cameras with lateral chromatic aberration.
+    fn generate_channel_ray(
+        &self,
+        s: f64,
+        t: f64,
+        lens: (f64, f64),
+        _channel: usize,
+    ) -> Option<Ray> {
+        self.generate_ray(s, t, lens)
+    }
+
+    /// Whether the color channels see along different rays.
+    fn is_chromatic(&self) -> bool {
+        false
+    }
+
+    /// Fraction of the light that reaches film position (s, t).
+    fn vignetting(&self, _s: f64, _t: f64) -> f64 {
+        1.0
+    }
+
+    /// Distance of `p` from the camera, as written to the depth AOV.
+    fn depth(&self, p: Point3) -> f64;
+}
+
+/// Where a camera is and how it's pointed, shared by every projection.
+#[derive(Clone)]
+pub struct CameraParams {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    /// Vertical field of view in degrees.
+    pub vfov: f64,
+    pub aperture: f64,
+    /// Shape of the lens opening, for perspective cameras.
+    pub aperture_shape: Aperture,
+    pub focus_dist: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Fisheye(FisheyeMapping),
+    Equirectangular,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" => Ok(Projection::Orthographic),
+            "fisheye" | "fisheye-equidistant" => {
+                Ok(Projection::Fisheye(FisheyeMapping::Equidistant))
+            }
+            "fisheye-equisolid" => Ok(Projection::Fisheye(FisheyeMapping::Equisolid)),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            _ => Err(format!("Unknown projection: {}", s)),
+        }
+    }
+}
+
+impl CameraParams {
+    /// Builds a camera with the given projection. A fisheye uses `vfov` as
+    /// the field of view across the image circle, the orthographic camera
+    /// frames what the perspective one would see at the focus distance.
+    pub fn build(&self, projection: Projection, aspect_ratio: f64) -> Box<dyn Camera> {
+        match projection {
+            Projection::Perspective => Box::new(
+                PerspectiveCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    self.vfov,
+                    aspect_ratio,
+                    self.aperture,
+                    self.focus_dist,
+                )
+                .with_aperture(self.aperture_shape.clone()),
+            ),
+            Projection::Orthographic => {
+                let height = 2.0 * self.focus_dist * (deg_to_rad(self.vfov) / 2.0).tan();
+                Box::new(OrthographicCamera::new(
+                    self.lookfrom,
+                    self.lookat,
+                    self.vup,
+                    height,
+                    aspect_ratio,
+                ))
+            }
+            Projection::Fisheye(mapping) => Box::new(FisheyeCamera::new(
+                self.lookfrom,
+                self.lookat,
+                self.vup,
+                self.vfov,
+                aspect_ratio,
+                mapping,
+            )),
+            Projection::Equirectangular => Box::new(EquirectangularCamera::new(
+                self.lookfrom,
+                self.lookat,
+                self.vup,
+            )),
+        }
+    }
+}
+
+/* ========================================== */
+/// Camera settings in photographic terms. Scene units are taken to be metres
+/// and radiance to be in cd/m², so renders expose like photographs taken
+/// with the same settings.
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalCamera {
+    /// Width of the sensor in mm, 36 for full frame.
+    pub sensor_width: f64,
+    /// Focal length in mm.
+    pub focal_length: f64,
+    /// Focal length over the aperture diameter.
+    pub f_stop: f64,
+    /// Exposure time in seconds.
+    pub shutter: f64,
+    pub iso: f64,
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self {
+        PhysicalCamera {
+            sensor_width: 36.0,
+            focal_length: 50.0,
+            f_stop: 8.0,
+            shutter: 1.0 / 60.0,
+            iso: 100.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    /// Vertical field of view in degrees, with the sensor's width spanning
+    /// the width of the frame.
+    pub fn vfov(&self, aspect_ratio: f64) -> f64 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        2.0 * (sensor_height / (2.0 * self.focal_length)).atan() * 180.0 / PI
+    }
+
+    /// Diameter of the aperture in metres.
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_stop / 1000.0
+    }
+
+    /// When the shutter opens and closes, in seconds.
+    pub fn shutter_interval(&self) -> (f64, f64) {
+        (0.0, self.shutter)
+    }
+
+    /// Exposure value at ISO 100 of these settings.
+    pub fn ev100(&self) -> f64 {
+        (self.f_stop * self.f_stop / self.shutter * 100.0 / self.iso).log2()
+    }
+
+    /// Factor scene radiance is multiplied by. Scenes are lit for the default
+    /// settings, which leave it unchanged: each stop of exposure away from
+    /// them doubles or halves it.
+    pub fn exposure_scale(&self) -> f64 {
+        2f64.powf(PhysicalCamera::default().ev100() - self.ev100())
+    }
+
+    /// Replaces the field of view and aperture of `params` with the ones of
+    /// this lens, keeping its position and focus distance.
+    pub fn apply(&self, params: &mut CameraParams, aspect_ratio: f64) {
+        params.vfov = self.vfov(aspect_ratio);
+        params.aperture = self.aperture();
+    }
+}
+
+/* ========================================== */
+/// Orthonormal basis of a camera: `w` points backwards, away from what the
+/// camera looks at, `u` to the right and `v` up.
+#[derive(Clone, Copy)]
+pub struct CameraFrame {
+    pub origin: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl CameraFrame {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3) -> Self {
+        let w = (lookfrom - lookat).unit_vector();
+        let u = vup.cross(w).unit_vector();
+        let v = w.cross(u);
+        CameraFrame {
+            origin: lookfrom,
+            u,
+            v,
+            w,
+        }
+    }
+
+    /// Turns camera space coordinates into a world space direction.
+    #[inline]
+    pub fn to_world(self, d: Vec3) -> Vec3 {
+        d.x() * self.u + d.y() * self.v + d.z() * self.w
+    }
+
+    /// Distance of `p` along the viewing direction.
+    #[inline]
+    pub fn depth(&self, p: Point3) -> f64 {
+        (self.origin - p).dot(&self.w)
+    }
+}
+
+/* ========================================== */
+/// Thin lens perspective camera.
+#[derive(Clone)]
+pub struct PerspectiveCamera {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
@@ -17,9 +244,12 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
+    focus_dist: f64,
+    aperture: Aperture,
+    aspect_ratio: f64,
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         lookfrom: Vec3,
         lookat: Vec3,
@@ -34,19 +264,14 @@ impl Camera {
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
 
-        let x: Vec3 = lookfrom - lookat;
+        let CameraFrame { origin, u, v, w } = CameraFrame::new(lookfrom, lookat, vup);
 
-        let w: Vec3 = x.unit_vector();
-        let u: Vec3 = vup.cross(w).unit_vector();
-        let v = w.cross(u);
-
-        let origin = lookfrom;
         let horizontal = focus_dist * viewport_width * u;
         let vertical = focus_dist * viewport_height * v;
         let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
 
         let lens_radius = aperture / 2.0;
-        Camera {
+        PerspectiveCamera {
             origin,
             horizontal,
             vertical,
@@ -55,17 +280,215 @@ impl Camera {
             v,
             w,
             lens_radius,
+            focus_dist,
+            aperture: Aperture::default(),
+            aspect_ratio,
         }
     }
+
+    /// The same camera with a differently shaped lens opening.
+    pub fn with_aperture(self, aperture: Aperture) -> Self {
+        PerspectiveCamera { aperture, ..self }
+    }
+
+    /// The same camera moved `shift` to the right, with its frustum sheared
+    /// back so that it still frames the same rectangle at distance
+    /// `convergence`. This is one eye of an off-axis stereo pair.
+    pub fn shifted_eye(&self, shift: f64, convergence: f64) -> Self {
+        let offset = shift * self.u;
+        PerspectiveCamera {
+            origin: self.origin + offset,
+            lower_left_corner: self.lower_left_corner
+                + (1.0 - self.focus_dist / convergence) * offset,
+            ..self.clone()
+        }
+    }
+
+    /// The ray through (s, t) on the screen, with the lens position taken
+    /// from a sample of the unit square and the shape of the aperture. `None`
+    /// when vignetted.
+    #[inline]
+    pub fn get_ray_sampled(&self, s: f64, t: f64, lens: (f64, f64)) -> Option<crate::ray::Ray> {
+        // Film position scaled so that the corners are at distance one.
+        let half_diagonal = (self.aspect_ratio * self.aspect_ratio + 1.0).sqrt();
+        let film = (
+            (2.0 * s - 1.0) * self.aspect_ratio / half_diagonal,
+            (2.0 * t - 1.0) / half_diagonal,
+        );
+        let (x, y) = self.aperture.sample(lens, film)?;
+        let rd: Vec3 = self.lens_radius * Vec3(x, y, 0.0);
+        Some(self.ray_through_lens(s, t, rd))
+    }
+
     #[inline]
-    pub fn get_ray(&self, s: f64, t: f64) -> crate::ray::Ray {
-        let rd: Vec3 = self.lens_radius * Vec3::random_in_unit_disk();
+    fn ray_through_lens(&self, s: f64, t: f64, rd: Vec3) -> crate::ray::Ray {
         let offset: Vec3 = self.u * rd.x() + self.v * rd.y();
         Ray {
-            orig: self.origin+offset,
+            orig: self.origin + offset,
             dir: self.lower_left_corner + s * self.horizontal + t * self.vertical
                 - self.origin
                 - offset,
+            wavelength: None,
+            time: 0.0,
+        }
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn generate_ray(&self, s: f64, t: f64, lens: (f64, f64)) -> Option<Ray> {
+        self.get_ray_sampled(s, t, lens)
+    }
+
+    fn depth(&self, p: Point3) -> f64 {
+        (self.origin - p).dot(&self.w)
+    }
+}
+
+/* ========================================== */
+/// Parallel rays over a `height` tall rectangle, for technical views.
+pub struct OrthographicCamera {
+    frame: CameraFrame,
+    width: f64,
+    height: f64,
+}
+
+impl OrthographicCamera {
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, height: f64, aspect_ratio: f64) -> Self {
+        OrthographicCamera {
+            frame: CameraFrame::new(lookfrom, lookat, vup),
+            width: height * aspect_ratio,
+            height,
         }
     }
 }
+
+impl Camera for OrthographicCamera {
+    fn generate_ray(&self, s: f64, t: f64, _lens: (f64, f64)) -> Option<Ray> {
+        let offset = Vec3((s - 0.5) * self.width, (t - 0.5) * self.height, 0.0);
+        Some(Ray::new(
+            self.frame.origin + self.frame.to_world(offset),
+            -self.frame.w,
+        ))
+    }
+
+    fn depth(&self, p: Point3) -> f64 {
+        self.frame.depth(p)
+    }
+}
+
+/* ========================================== */
+/// How a fisheye lens maps the angle off its axis to the distance from the
+/// center of the image circle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FisheyeMapping {
+    /// Distance proportional to the angle.
+    Equidistant,
+    /// Distance proportional to sin(angle / 2), which preserves areas.
+    Equisolid,
+}
+
+/// A fisheye whose image circle fills the height of the frame, seeing `fov`
+/// degrees across it. Outside the circle it sees nothing.
+pub struct FisheyeCamera {
+    frame: CameraFrame,
+    aspect_ratio: f64,
+    half_fov: f64,
+    mapping: FisheyeMapping,
+}
+
+impl FisheyeCamera {
+    pub fn new(
+        lookfrom: Vec3,
+        lookat: Vec3,
+        vup: Vec3,
+        fov: f64,
+        aspect_ratio: f64,
+        mapping: FisheyeMapping,
+    ) -> Self {
+        FisheyeCamera {
+            frame: CameraFrame::new(lookfrom, lookat, vup),
+            aspect_ratio,
+            half_fov: deg_to_rad(fov.min(360.0)) / 2.0,
+            mapping,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn generate_ray(&self, s: f64, t: f64, _lens: (f64, f64)) -> Option<Ray> {
+        // Image circle of radius one, centered on the frame.
+        let x = (2.0 * s - 1.0) * self.aspect_ratio;
+        let y = 2.0 * t - 1.0;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.half_fov,
+            FisheyeMapping::Equisolid => {
+                2.0 * clamp(r * (self.half_fov / 2.0).sin(), -1.0, 1.0).asin()
+            }
+        };
+        let phi = f64::atan2(y, x);
+        let local = Vec3(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+        );
+        Some(Ray::new(self.frame.origin, self.frame.to_world(local)))
+    }
+
+    fn depth(&self, p: Point3) -> f64 {
+        (p - self.frame.origin).length()
+    }
+}
+
+/* ========================================== */
+/// A full 360° x 180° panorama in latitude-longitude layout, looking at
+/// `lookat` in the middle of the frame. Best rendered at a 2:1 aspect ratio.
+pub struct EquirectangularCamera {
+    frame: CameraFrame,
+    /// Signed distance of the eye from the center for omnidirectional stereo,
+    /// negative for the left eye. Zero for a mono panorama.
+    eye_offset: f64,
+}
+
+impl EquirectangularCamera {
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3) -> Self {
+        EquirectangularCamera::omnidirectional_eye(lookfrom, lookat, vup, 0.0)
+    }
+
+    /// One eye of an omnidirectional stereo (ODS) panorama: every ray starts
+    /// `eye_offset` from the center, on the circle tangent to its direction.
+    pub fn omnidirectional_eye(lookfrom: Vec3, lookat: Vec3, vup: Vec3, eye_offset: f64) -> Self {
+        EquirectangularCamera {
+            frame: CameraFrame::new(lookfrom, lookat, vup),
+            eye_offset,
+        }
+    }
+
+    /// Direction in camera space for film position (s, t).
+    pub fn local_direction(s: f64, t: f64) -> Vec3 {
+        let phi = (s - 0.5) * 2.0 * PI;
+        let theta = (t - 0.5) * PI;
+        Vec3(
+            theta.cos() * phi.sin(),
+            theta.sin(),
+            -theta.cos() * phi.cos(),
+        )
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn generate_ray(&self, s: f64, t: f64, _lens: (f64, f64)) -> Option<Ray> {
+        let dir = EquirectangularCamera::local_direction(s, t);
+        let phi = (s - 0.5) * 2.0 * PI;
+        let right = Vec3(phi.cos(), 0.0, phi.sin());
+        let origin = self.frame.origin + self.eye_offset * self.frame.to_world(right);
+        Some(Ray::new(origin, self.frame.to_world(dir)))
+    }
+
+    fn depth(&self, p: Point3) -> f64 {
+        (p - self.frame.origin).length()
+    }
+}
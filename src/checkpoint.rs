@@ -0,0 +1,96 @@
+use crate::film::Film;
+use crate::rng::RngState;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 3;
+
+/// Everything needed to pick up an interrupted render where it stopped: the
+/// accumulated film with its sample counts, the random number generator and
+/// what the render was set up with.
+pub struct Checkpoint {
+    /// `RenderOptions::settings_hash` of the render that wrote it.
+    pub settings_hash: u64,
+    /// Seed the scene was built from.
+    pub seed: u64,
+    pub rng_state: RngState,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    /// Saves to a temporary file and renames it over `path`, so a crash while
+    /// saving never destroys the previous checkpoint.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut out = io::BufWriter::new(std::fs::File::create(&tmp)?);
+        self.write(&mut out)?;
+        out.flush()?;
+        drop(out);
+        std::fs::rename(&tmp, path)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Checkpoint::read(&mut io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&self.settings_hash.to_le_bytes())?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        for word in self.rng_state.iter() {
+            out.write_all(&word.to_le_bytes())?;
+        }
+        self.film.write_raw(out)
+    }
+
+    pub fn read(input: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a checkpoint file",
+            ));
+        }
+        let version = read_u32(input)?;
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported checkpoint version {}", version),
+            ));
+        }
+        let settings_hash = read_u64(input)?;
+        let seed = read_u64(input)?;
+        let mut rng_state = [0u64; 4];
+        for word in rng_state.iter_mut() {
+            *word = read_u64(input)?;
+        }
+        let film = Film::read_raw(input)?;
+        Ok(Checkpoint {
+            settings_hash,
+            seed,
+            rng_state,
+            film,
+        })
+    }
+}
+
+pub fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    read_u64(input).map(f64::from_bits)
+}
@@ -1,17 +1,21 @@
 use crate::clamp;
+use crate::tonemap::DisplayTransform;
 use crate::vec3::*;
 use std::io::Write;
 
 pub type Color = Vec3;
 
+/// Writes the average of `samples_per_pixel` summed samples, passed through
+/// the display transform, as an 8-bit PPM triple.
 pub fn write_color(
     out: &mut impl Write,
     pixel: Color,
     samples_per_pixel: i32,
+    display: &DisplayTransform,
 ) -> std::io::Result<()> {
 
-    let static_cast =
-        |x: f64| (256.0 * clamp(f64::sqrt(x / samples_per_pixel as f64), 0.0, 0.999)) as i32;
+    let pixel = display.apply(pixel / samples_per_pixel as f64);
+    let static_cast = |x: f64| (256.0 * clamp(x, 0.0, 0.999)) as i32;
 
         out.write(
         format!(
@@ -24,3 +28,10 @@ pub fn write_color(
     )?;
     Ok(())
 }
+
+/// The 8-bit display values of a linear pixel, as `write_color` writes them.
+pub fn display_bytes(pixel: Color, display: &DisplayTransform) -> [u8; 3] {
+    let pixel = display.apply(pixel);
+    let static_cast = |x: f64| (256.0 * clamp(x, 0.0, 0.999)) as u8;
+    [static_cast(pixel.0), static_cast(pixel.1), static_cast(pixel.2)]
+}
@@ -0,0 +1,121 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::INFINITY;
+use std::rc::Rc;
+
+/// How the solids of a `Csg` are combined.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CsgOperation {
+    /// Inside either solid.
+    Union,
+    /// Inside both solids.
+    Intersection,
+    /// Inside the left solid but not the right one.
+    Difference,
+}
+
+impl CsgOperation {
+    #[inline]
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Boolean combination of two closed hittables, e.g. a sphere with a
+/// cylindrical hole drilled through it. Works on the intervals the ray spends
+/// inside each of them, from their `hit_all` crossings.
+pub struct Csg {
+    left: Rc<dyn Hittable>,
+    right: Rc<dyn Hittable>,
+    operation: CsgOperation,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: &Rc<dyn Hittable>, right: &Rc<dyn Hittable>) -> Self {
+        Csg {
+            left: left.clone(),
+            right: right.clone(),
+            operation,
+        }
+    }
+
+    /// The crossings of the combined surface from `t_min` on, stopping after
+    /// the first one when `first_only`.
+    fn crossings(&self, r: &Ray, t_min: f64, t_max: f64, first_only: bool) -> Vec<HitRecord> {
+        // Crossings past t_max still matter for knowing where the ray
+        // starts: a closed solid left by the first crossing was entered
+        // before t_min.
+        let left = self.left.hit_all(r, t_min, INFINITY);
+        let right = self.right.hit_all(r, t_min, INFINITY);
+        let mut in_left = left.first().is_some_and(|rec| !rec.front_face);
+        let mut in_right = right.first().is_some_and(|rec| !rec.front_face);
+
+        let mut crossings = Vec::new();
+        let (mut lefts, mut rights) = (left.iter().peekable(), right.iter().peekable());
+        loop {
+            let from_left = match (lefts.peek(), rights.peek()) {
+                (Some(a), Some(b)) => a.t <= b.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let was_inside = self.operation.inside(in_left, in_right);
+            let rec = if from_left {
+                in_left = !in_left;
+                lefts.next().unwrap()
+            } else {
+                in_right = !in_right;
+                rights.next().unwrap()
+            };
+            if rec.t > t_max {
+                break;
+            }
+            let inside = self.operation.inside(in_left, in_right);
+            if inside != was_inside {
+                // Entering the combined solid is a front face hit, whichever
+                // surface it's through: the inner wall of a hole faces out.
+                let mut rec = rec.clone();
+                rec.front_face = inside;
+                crossings.push(rec);
+                if first_only {
+                    break;
+                }
+            }
+        }
+        crossings
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.crossings(r, t_min, t_max, true).pop()
+    }
+
+    fn hit_all(&self, r: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        self.crossings(r, t_min, t_max, false)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let left = self.left.bounding_box(time0, time1);
+        match self.operation {
+            CsgOperation::Union => {
+                let right = self.right.bounding_box(time0, time1)?;
+                Some(left?.surrounding(&right))
+            }
+            // Never bigger than the left solid.
+            CsgOperation::Intersection | CsgOperation::Difference => left,
+        }
+    }
+}
+
+impl std::convert::From<Csg> for Rc<dyn Hittable> {
+    fn from(csg: Csg) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(csg);
+        trait_object
+    }
+}
@@ -0,0 +1,473 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable, HittableList},
+    material::Material,
+    ray::Ray,
+    vec3::*,
+};
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// How the width of a `Curve` is spread out in space.
+#[derive(Clone, Copy)]
+pub enum CurveType {
+    /// A flat strip. Without normals it always faces the ray, which is what
+    /// `Hair` expects; with them it's oriented by the normals at both ends,
+    /// interpolated along the curve.
+    Ribbon(Option<(Vec3, Vec3)>),
+    /// A flat strip facing the ray, shaded as a round tube: much cheaper than
+    /// a real tube, and indistinguishable at hair widths.
+    Cylinder,
+}
+
+impl FromStr for CurveType {
+    type Err = String;
+
+    /// Parses `ribbon` or `cylinder`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ribbon" => Ok(CurveType::Ribbon(None)),
+            "cylinder" => Ok(CurveType::Cylinder),
+            _ => Err(format!("Unknown curve type: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for CurveType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CurveType::Ribbon(None) => write!(f, "ribbon"),
+            CurveType::Ribbon(Some((n0, n1))) => write!(
+                f,
+                "ribbon {} {} {} {} {} {}",
+                n0.0, n0.1, n0.2, n1.0, n1.1, n1.2
+            ),
+            CurveType::Cylinder => write!(f, "cylinder"),
+        }
+    }
+}
+
+/// A cubic Bezier curve with a width varying linearly along it, for hair,
+/// fur and grass. Intersected directly by recursively splitting the curve in
+/// a coordinate system looking down the ray, after pbrt.
+///
+/// u goes along the curve, v across it, so `dpdu` is the tangent as hair
+/// shading expects.
+pub struct Curve {
+    control_points: [Point3; 4],
+    /// Width at both ends.
+    width: (f64, f64),
+    curve_type: CurveType,
+    /// Angle between the ribbon normals and its inverse sine, for slerping.
+    normal_angle: (f64, f64),
+    bounds: Aabb,
+    pub mat_ptr: Rc<dyn Material>,
+}
+
+impl Curve {
+    pub fn new(
+        control_points: [Point3; 4],
+        width: (f64, f64),
+        curve_type: CurveType,
+        m: &Rc<dyn Material>,
+    ) -> Self {
+        let normal_angle = match curve_type {
+            CurveType::Ribbon(Some((n0, n1))) => {
+                let angle = n0
+                    .unit_vector()
+                    .dot(&n1.unit_vector())
+                    .clamp(-1.0, 1.0)
+                    .acos();
+                (angle, 1.0 / angle.sin())
+            }
+            _ => (0.0, 0.0),
+        };
+        let cp = &control_points;
+        let half_width = 0.5 * width.0.max(width.1);
+        let hull = Aabb::new(cp[0], cp[1]).surrounding(&Aabb::new(cp[2], cp[3]));
+        let pad = Vec3(half_width, half_width, half_width);
+        let bounds = Aabb::new(hull.min - pad, hull.max + pad);
+        Curve {
+            control_points,
+            width,
+            curve_type,
+            normal_angle,
+            bounds,
+            mat_ptr: m.clone(),
+        }
+    }
+
+    /// Loads a groom: one curve per line, as the four control points and the
+    /// width at the start and end, or one width for both. Ribbons can be
+    /// followed by the normals at both ends to orient them.
+    ///
+    /// ```text
+    /// # x0 y0 z0  x1 y1 z1  x2 y2 z2  x3 y3 z3  width0 width1  [n0 n1]
+    /// 0 0 0  0 0.3 0.05  0 0.6 0.2  0.05 0.8 0.4  0.004 0.001
+    /// 0 0 0  0 0.5 0  0 1 0  0 1.5 0  0.1  1 0 0  0 0 1
+    /// ```
+    pub fn load(
+        path: impl AsRef<Path>,
+        curve_type: CurveType,
+        m: &Rc<dyn Material>,
+    ) -> io::Result<HittableList> {
+        let mut curves = HittableList::new();
+        let text = std::fs::read_to_string(path)?;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Line {}: {}", number + 1, message),
+                )
+            };
+            let values = line
+                .split_whitespace()
+                .map(|v| {
+                    v.parse()
+                        .map_err(|_| invalid(format!("invalid number {}", v)))
+                })
+                .collect::<io::Result<Vec<f64>>>()?;
+            let (width, normals) = match values.len() {
+                13 | 19 => ((values[12], values[12]), &values[13..]),
+                14 | 20 => ((values[12], values[13]), &values[14..]),
+                _ => {
+                    return Err(invalid(
+                        "expected 12 coordinates, 1 or 2 widths and optional normals".to_string(),
+                    ))
+                }
+            };
+            let point = |i: usize| Vec3(values[3 * i], values[3 * i + 1], values[3 * i + 2]);
+            let curve_type = match (curve_type, normals) {
+                (CurveType::Ribbon(_), &[x0, y0, z0, x1, y1, z1]) => {
+                    CurveType::Ribbon(Some((Vec3(x0, y0, z0), Vec3(x1, y1, z1))))
+                }
+                (CurveType::Cylinder, &[_, _, _, _, _, _]) => {
+                    return Err(invalid("cylinders can't have normals".to_string()))
+                }
+                _ => curve_type,
+            };
+            let curve = Curve::new(
+                [point(0), point(1), point(2), point(3)],
+                width,
+                curve_type,
+                m,
+            );
+            curves.add(&curve.into());
+        }
+        Ok(curves)
+    }
+
+    #[inline]
+    fn width_at(&self, u: f64) -> f64 {
+        self.width.0 + u * (self.width.1 - self.width.0)
+    }
+
+    /// The ribbon normal at `u`, slerped between the end normals.
+    fn ribbon_normal(&self, u: f64, (n0, n1): (Vec3, Vec3)) -> Vec3 {
+        let (angle, inv_sin) = self.normal_angle;
+        if angle < 1e-6 {
+            return n0.unit_vector();
+        }
+        if std::f64::consts::PI - angle < 1e-6 {
+            // Opposite normals leave no plane to slerp in, so the ribbon
+            // turns half a turn about its tangent instead.
+            let (_, tangent) = eval_bezier(&self.control_points, u);
+            return rotate_about(
+                n0.unit_vector(),
+                tangent.unit_vector(),
+                u * std::f64::consts::PI,
+            );
+        }
+        let s0 = ((1.0 - u) * angle).sin() * inv_sin;
+        let s1 = (u * angle).sin() * inv_sin;
+        s0 * n0.unit_vector() + s1 * n1.unit_vector()
+    }
+
+    /// Looks for a hit with the part of the curve between `u0` and `u1`,
+    /// whose control points are `cp` in ray space, nearer than `z_max`
+    /// along the ray. Returns the distance along the ray, u and v.
+    fn recursive_hit(
+        &self,
+        cp: &[Vec3; 4],
+        ray: &RaySpace,
+        (u0, u1): (f64, f64),
+        z_max: f64,
+        depth: u32,
+    ) -> Option<(f64, f64, f64)> {
+        // Skip the segment when its widened bounds miss the ray, which runs
+        // along the z axis.
+        let half_width = 0.5 * self.width_at(u0).max(self.width_at(u1));
+        let bounds = Aabb::new(cp[0], cp[1]).surrounding(&Aabb::new(cp[2], cp[3]));
+        if bounds.min.0 - half_width > 0.0
+            || bounds.max.0 + half_width < 0.0
+            || bounds.min.1 - half_width > 0.0
+            || bounds.max.1 + half_width < 0.0
+            || bounds.min.2 - half_width > z_max
+            || bounds.max.2 + half_width < 0.0
+        {
+            return None;
+        }
+
+        if depth > 0 {
+            let halves = split_bezier(cp);
+            let u_mid = 0.5 * (u0 + u1);
+            let first = self.recursive_hit(&halves.0, ray, (u0, u_mid), z_max, depth - 1);
+            let z_max = first.map_or(z_max, |(z, _, _)| z);
+            let second = self.recursive_hit(&halves.1, ray, (u_mid, u1), z_max, depth - 1);
+            return second.or(first);
+        }
+
+        // The segment is nearly straight now: find the point on it closest
+        // to the ray, rejecting those past its ends.
+        let edge = (cp[1].1 - cp[0].1) * -cp[0].1 + cp[0].0 * (cp[0].0 - cp[1].0);
+        if edge < 0.0 {
+            return None;
+        }
+        let edge = (cp[2].1 - cp[3].1) * -cp[3].1 + cp[3].0 * (cp[3].0 - cp[2].0);
+        if edge < 0.0 {
+            return None;
+        }
+        let segment = (cp[3].0 - cp[0].0, cp[3].1 - cp[0].1);
+        let denom = segment.0 * segment.0 + segment.1 * segment.1;
+        if denom == 0.0 {
+            return None;
+        }
+        let w = -(cp[0].0 * segment.0 + cp[0].1 * segment.1) / denom;
+        let u = (u0 + w * (u1 - u0)).clamp(u0, u1);
+
+        let mut hit_width = self.width_at(u);
+        if let CurveType::Ribbon(Some(normals)) = self.curve_type {
+            // A ribbon seen edge on is thinner.
+            hit_width *= self
+                .ribbon_normal(u, normals)
+                .unit_vector()
+                .dot(&ray.direction)
+                .abs();
+        }
+        let (pc, dpcdw) = eval_bezier(cp, w.clamp(0.0, 1.0));
+        let dist2 = pc.0 * pc.0 + pc.1 * pc.1;
+        if dist2 > hit_width * hit_width * 0.25 || pc.2 < 0.0 || pc.2 > z_max {
+            return None;
+        }
+        // Which side of the curve the ray passes on.
+        let dist = dist2.sqrt();
+        let side = dpcdw.0 * -pc.1 + pc.0 * dpcdw.1;
+        let v = if side > 0.0 {
+            0.5 + dist / hit_width
+        } else {
+            0.5 - dist / hit_width
+        };
+        Some((pc.2, u, v))
+    }
+}
+
+/// A coordinate system with the ray's origin at its origin, looking down
+/// the ray along +z.
+struct RaySpace {
+    origin: Point3,
+    x: Vec3,
+    y: Vec3,
+    /// The ray's unit direction.
+    direction: Vec3,
+}
+
+impl RaySpace {
+    /// `hint` is a direction to line up the x axis across, when it isn't
+    /// parallel to the ray.
+    fn new(r: &Ray, hint: Vec3) -> Self {
+        let direction = r.dir().unit_vector();
+        let mut x = hint.cross(direction);
+        if x.length_squared() < 1e-20 {
+            // Any axis perpendicular to the ray will do.
+            let other = if direction.0.abs() > 0.9 {
+                Vec3(0.0, 1.0, 0.0)
+            } else {
+                Vec3(1.0, 0.0, 0.0)
+            };
+            x = other.cross(direction);
+        }
+        let x = x.unit_vector();
+        RaySpace {
+            origin: r.orig(),
+            x,
+            y: direction.cross(x),
+            direction,
+        }
+    }
+
+    #[inline]
+    fn to_local(&self, p: Point3) -> Vec3 {
+        let d = p - self.origin;
+        Vec3(d.dot(&self.x), d.dot(&self.y), d.dot(&self.direction))
+    }
+
+    #[inline]
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        v.0 * self.x + v.1 * self.y + v.2 * self.direction
+    }
+}
+
+/// The point and derivative of a cubic Bezier curve at `u`, by de Casteljau.
+fn eval_bezier(cp: &[Vec3; 4], u: f64) -> (Vec3, Vec3) {
+    let lerp = |a: Vec3, b: Vec3| a + u * (b - a);
+    let cp1 = [lerp(cp[0], cp[1]), lerp(cp[1], cp[2]), lerp(cp[2], cp[3])];
+    let cp2 = [lerp(cp1[0], cp1[1]), lerp(cp1[1], cp1[2])];
+    let derivative = if (cp2[1] - cp2[0]).length_squared() > 0.0 {
+        3.0 * (cp2[1] - cp2[0])
+    } else {
+        // Coinciding control points leave the tangent to the chord.
+        cp[3] - cp[0]
+    };
+    (lerp(cp2[0], cp2[1]), derivative)
+}
+
+/// The two halves of a cubic Bezier curve.
+fn split_bezier(cp: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let mid = |a: Vec3, b: Vec3| 0.5 * (a + b);
+    let (a, b, c) = (mid(cp[0], cp[1]), mid(cp[1], cp[2]), mid(cp[2], cp[3]));
+    let (d, e) = (mid(a, b), mid(b, c));
+    let f = mid(d, e);
+    ([cp[0], a, d, f], [f, e, c, cp[3]])
+}
+
+impl Hittable for Curve {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bounds.hit(r, t_min, t_max)?;
+        let cp = &self.control_points;
+        let ray = RaySpace::new(r, cp[3] - cp[0]);
+        let local = [
+            ray.to_local(cp[0]),
+            ray.to_local(cp[1]),
+            ray.to_local(cp[2]),
+            ray.to_local(cp[3]),
+        ];
+
+        // Split until the segments are within a small fraction of the
+        // width from straight, based on the curve's second differences.
+        let second_difference = (0..2)
+            .map(|i| {
+                let d = local[i] - 2.0 * local[i + 1] + local[i + 2];
+                d.0.abs().max(d.1.abs()).max(d.2.abs())
+            })
+            .fold(0.0, f64::max);
+        let eps = 0.05 * self.width.0.max(self.width.1);
+        let depth = if second_difference > 0.0 {
+            (0.5 * (std::f64::consts::SQRT_2 * 6.0 * second_difference / (8.0 * eps)).log2())
+                .round()
+                .clamp(0.0, 10.0) as u32
+        } else {
+            0
+        };
+
+        let length = r.dir().length();
+        let (z, u, v) = self.recursive_hit(&local, &ray, (0.0, 1.0), t_max * length, depth)?;
+        let t = z / length;
+        if t < t_min {
+            return None;
+        }
+
+        let mut rec = HitRecord {
+            t,
+            p: r.at(t),
+            u,
+            v,
+            ..Default::default()
+        };
+        let width = self.width_at(u);
+        let (_, dpdu) = eval_bezier(cp, u);
+        let dpdv = match self.curve_type {
+            CurveType::Ribbon(Some(normals)) => {
+                self.ribbon_normal(u, normals).cross(dpdu).unit_vector() * width
+            }
+            _ => {
+                // Across the curve, in the plane facing the ray.
+                let tangent = ray.to_local(r.orig() + dpdu);
+                let across = Vec3(-tangent.1, tangent.0, 0.0).unit_vector() * width;
+                ray.to_world(across)
+            }
+        };
+        // A cylinder bends the normal around the tangent, like on a tube, but
+        // keeps the flat frame in dpdv: `Hair` works out the offset across
+        // the fiber from v itself.
+        let across = match self.curve_type {
+            // Turned the opposite way to pbrt's Rotate(-theta, dpdu), as the
+            // normal is flipped to face the ray here: it leans away from the
+            // axis towards the edge that was hit, see the tests.
+            CurveType::Cylinder => {
+                let angle = crate::camera::deg_to_rad(-180.0 * v + 90.0);
+                rotate_about(dpdv, dpdu.unit_vector(), -angle)
+            }
+            _ => dpdv,
+        };
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
+        rec.set_normal_face(r, dpdu.cross(across).unit_vector());
+        rec.mat_ptr = Some(self.mat_ptr.clone());
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+/// Rotates `v` by `angle` radians about the unit `axis`, by Rodrigues' formula.
+fn rotate_about(v: Vec3, axis: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    cos * v + sin * axis.cross(v) + (1.0 - cos) * axis.dot(&v) * axis
+}
+
+impl std::convert::From<Curve> for Rc<dyn Hittable> {
+    fn from(curve: Curve) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(curve);
+        trait_object
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    /// Hits a straight vertical curve seen from +z, `offset` across from its
+    /// axis.
+    fn hit_across(curve_type: CurveType, offset: f64) -> HitRecord {
+        let points = [0.0, 1.0, 2.0, 3.0].map(|y| Vec3(0.0, y, 0.0));
+        let m = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+        let curve = Curve::new(points, (0.2, 0.2), curve_type, &m);
+        let r = Ray::new(Vec3(offset, 1.5, 5.0), Vec3(0.0, 0.0, -1.0));
+        curve.hit(&r, 0.001, f64::INFINITY).unwrap()
+    }
+
+    #[test]
+    fn cylinder_normals_point_away_from_the_axis() {
+        for &offset in &[-0.099, 0.099] {
+            let rec = hit_across(CurveType::Cylinder, offset);
+            assert!(rec.v < 0.01 || rec.v > 0.99, "v = {}", rec.v);
+            assert!(rec.normal.z() > 0.0);
+            assert!(rec.normal.x() * offset > 0.0, "offset {}", offset);
+            assert!(rec.normal.x().abs() > 0.99, "offset {}", offset);
+        }
+        let rec = hit_across(CurveType::Cylinder, 0.0);
+        assert!(rec.normal.z() > 0.99);
+    }
+
+    #[test]
+    fn opposite_ribbon_normals_stay_finite() {
+        let points = [0.0, 1.0, 2.0, 3.0].map(|y| Vec3(0.0, y, 0.0));
+        let normals = (Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, -1.0));
+        let m = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+        let curve = Curve::new(points, (0.2, 0.2), CurveType::Ribbon(Some(normals)), &m);
+        for &u in &[0.0, 0.25, 0.5, 0.75, 1.0] {
+            let n = curve.ribbon_normal(u, normals);
+            assert!((n.length() - 1.0).abs() < 1e-9, "u = {}", u);
+        }
+        assert!(curve.ribbon_normal(1.0, normals).z() < -0.999);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    aabb::Aabb,
+    camera::PI,
+    disk::Disk,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    vec3::*,
+};
+use std::rc::Rc;
+
+/// An upright cylinder standing on `base`, optionally closed with disks at
+/// both ends.
+pub struct Cylinder {
+    /// Center of the bottom end.
+    pub base: Point3,
+    pub radius: f64,
+    pub height: f64,
+    /// The bottom and top caps, when closed.
+    caps: Option<(Disk, Disk)>,
+    pub mat_ptr: Rc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, radius: f64, height: f64, capped: bool, m: &Rc<dyn Material>) -> Self {
+        let caps = if capped {
+            let top = base + Vec3(0.0, height, 0.0);
+            Some((Disk::new(base, radius, m), Disk::new(top, radius, m)))
+        } else {
+            None
+        };
+        Cylinder {
+            base,
+            radius,
+            height,
+            caps,
+            mat_ptr: m.clone(),
+        }
+    }
+
+    fn hit_side(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let o = r.orig() - self.base;
+        let d = r.dir();
+        let a = d.x() * d.x() + d.z() * d.z();
+        if a == 0.0 {
+            return None;
+        }
+        let half_b = o.x() * d.x() + o.z() * d.z();
+        let c = o.x() * o.x() + o.z() * o.z() - self.radius * self.radius;
+        let disc = half_b * half_b - a * c;
+        if disc < 0.0 {
+            return None;
+        }
+        let sqrtd = disc.sqrt();
+        let root = [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+            .iter()
+            .copied()
+            .find(|&t| {
+                let y = o.y() + t * d.y();
+                t_min <= t && t <= t_max && (0.0..=self.height).contains(&y)
+            })?;
+
+        let mut rec = HitRecord {
+            t: root,
+            p: r.at(root),
+            ..Default::default()
+        };
+        let q = rec.p - self.base;
+        rec.set_normal_face(r, Vec3(q.x(), 0.0, q.z()) / self.radius);
+        rec.u = (f64::atan2(-q.z(), q.x()) + PI) / (2.0 * PI);
+        rec.v = q.y() / self.height;
+        rec.dpdu = 2.0 * PI * Vec3(q.z(), 0.0, -q.x());
+        rec.dpdv = Vec3(0.0, self.height, 0.0);
+        rec.mat_ptr = Some(self.mat_ptr.clone());
+        Some(rec)
+    }
+}
+
+/// Nearest hit of the caps of a cylinder or cone, given the nearest hit of
+/// its side. The bottom cap faces downwards.
+fn hit_caps(
+    side: Option<HitRecord>,
+    bottom: Option<&Disk>,
+    top: Option<&Disk>,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord> {
+    let mut closest_so_far = side.as_ref().map_or(t_max, |rec| rec.t);
+    let mut nearest = side;
+    if let Some(mut rec) = bottom.and_then(|cap| cap.hit(r, t_min, closest_so_far)) {
+        rec.front_face = !rec.front_face;
+        closest_so_far = rec.t;
+        nearest = Some(rec);
+    }
+    if let Some(rec) = top.and_then(|cap| cap.hit(r, t_min, closest_so_far)) {
+        nearest = Some(rec);
+    }
+    nearest
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let side = self.hit_side(r, t_min, t_max);
+        match self.caps {
+            Some((ref bottom, ref top)) => hit_caps(side, Some(bottom), Some(top), r, t_min, t_max),
+            None => side,
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.base - Vec3(self.radius, 0.0, self.radius),
+            self.base + Vec3(self.radius, self.height, self.radius),
+        ))
+    }
+}
+
+impl std::convert::From<Cylinder> for Rc<dyn Hittable> {
+    fn from(cylinder: Cylinder) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(cylinder);
+        trait_object
+    }
+}
+
+/* ========================================== */
+/// An upright cone standing on `base`, narrowing to its apex `height` above
+/// it. Optionally closed with a disk at the bottom.
+pub struct Cone {
+    /// Center of the bottom end.
+    pub base: Point3,
+    pub radius: f64,
+    pub height: f64,
+    cap: Option<Disk>,
+    pub mat_ptr: Rc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(base: Point3, radius: f64, height: f64, capped: bool, m: &Rc<dyn Material>) -> Self {
+        Cone {
+            base,
+            radius,
+            height,
+            cap: if capped {
+                Some(Disk::new(base, radius, m))
+            } else {
+                None
+            },
+            mat_ptr: m.clone(),
+        }
+    }
+
+    fn hit_side(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // x² + z² = k² (h - y)², with k the radius shrinking per unit height.
+        let o = r.orig() - self.base;
+        let d = r.dir();
+        let h = self.height;
+        let k2 = (self.radius / h) * (self.radius / h);
+        let a = d.x() * d.x() + d.z() * d.z() - k2 * d.y() * d.y();
+        let half_b = o.x() * d.x() + o.z() * d.z() + k2 * (h - o.y()) * d.y();
+        let c = o.x() * o.x() + o.z() * o.z() - k2 * (h - o.y()) * (h - o.y());
+
+        let roots = if a.abs() < 1e-12 {
+            // Parallel to the slope: only one crossing.
+            if half_b == 0.0 {
+                return None;
+            }
+            [-c / (2.0 * half_b), f64::INFINITY]
+        } else {
+            let disc = half_b * half_b - a * c;
+            if disc < 0.0 {
+                return None;
+            }
+            let sqrtd = disc.sqrt();
+            let (t0, t1) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+            [t0.min(t1), t0.max(t1)]
+        };
+        // The equation also holds on the mirrored cone above the apex.
+        let root = roots.iter().copied().find(|&t| {
+            let y = o.y() + t * d.y();
+            t_min <= t && t <= t_max && (0.0..=h).contains(&y)
+        })?;
+
+        let mut rec = HitRecord {
+            t: root,
+            p: r.at(root),
+            ..Default::default()
+        };
+        let q = rec.p - self.base;
+        let outward = Vec3(q.x(), k2 * (h - q.y()), q.z()).unit_vector();
+        rec.set_normal_face(r, outward);
+        rec.u = (f64::atan2(-q.z(), q.x()) + PI) / (2.0 * PI);
+        rec.v = q.y() / h;
+        rec.dpdu = 2.0 * PI * Vec3(q.z(), 0.0, -q.x());
+        // Clamped so the frame stays well defined at the apex.
+        let rest = (1.0 - rec.v).max(1e-6);
+        rec.dpdv = Vec3(-q.x() / rest, h, -q.z() / rest);
+        rec.mat_ptr = Some(self.mat_ptr.clone());
+        Some(rec)
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let side = self.hit_side(r, t_min, t_max);
+        hit_caps(side, self.cap.as_ref(), None, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.base - Vec3(self.radius, 0.0, self.radius),
+            self.base + Vec3(self.radius, self.height, self.radius),
+        ))
+    }
+}
+
+impl std::convert::From<Cone> for Rc<dyn Hittable> {
+    fn from(cone: Cone) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(cone);
+        trait_object
+    }
+}
@@ -0,0 +1,126 @@
+use crate::aov::AovBuffers;
+use crate::color::Color;
+use crate::film::Film;
+use crate::vec3::Vec3;
+
+/// Edge stopping strengths of the denoiser. Smaller values keep edges
+/// sharper at the cost of leaving more noise.
+#[derive(Clone, Copy, Debug)]
+pub struct DenoiseSettings {
+    /// Number of à-trous passes, each doubling the filter footprint.
+    pub iterations: u32,
+    pub sigma_color: f64,
+    pub sigma_normal: f64,
+    /// Relative to the depth of the center pixel.
+    pub sigma_depth: f64,
+    pub sigma_albedo: f64,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+/// B3 spline taps of the à-trous wavelet transform.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Denoises the film in place with an edge-avoiding à-trous wavelet filter
+/// (Dammertz et al. 2010), guided by the albedo, normal and depth AOVs.
+///
+/// The color is divided by the albedo before filtering and multiplied back
+/// afterwards, so texture detail isn't blurred along with the noise. Works
+/// on the linear HDR values, before the display transform.
+pub fn denoise(film: &mut Film, settings: &DenoiseSettings) {
+    let aovs = match film.aovs {
+        Some(ref aovs) => aovs,
+        None => return,
+    };
+    let (width, height) = (film.width, film.height);
+    let eps = 1e-3;
+
+    let albedo_safe = |a: Color| Vec3(a.0.max(eps), a.1.max(eps), a.2.max(eps));
+    let divide = |c: Color, a: Color| Vec3(c.0 / a.0, c.1 / a.1, c.2 / a.2);
+    let mut irradiance: Vec<Color> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let i = (y * width + x) as usize;
+            divide(film.pixel(x, y).color(), albedo_safe(aovs.albedo[i]))
+        })
+        .collect();
+
+    let mut sigma_color = settings.sigma_color;
+    for iteration in 0..settings.iterations {
+        // Once the taps are spread wider than the image there's nothing
+        // left to filter.
+        let step = match 1i32.checked_shl(iteration) {
+            Some(step) if step > 0 && step < width.max(height) => step,
+            _ => break,
+        };
+        irradiance = atrous_pass(&irradiance, aovs, step, sigma_color, settings);
+        // Later passes see smoother input, so they stop at smaller differences.
+        sigma_color *= 0.5;
+    }
+
+    let denoised: Vec<Color> = irradiance
+        .iter()
+        .zip(aovs.albedo.iter())
+        .map(|(&e, &a)| e * albedo_safe(a))
+        .collect();
+    for y in 0..height {
+        for x in 0..width {
+            film.set_color(x, y, denoised[(y * width + x) as usize]);
+        }
+    }
+}
+
+fn atrous_pass(
+    input: &[Color],
+    aovs: &AovBuffers,
+    step: i32,
+    sigma_color: f64,
+    settings: &DenoiseSettings,
+) -> Vec<Color> {
+    let (width, height) = (aovs.width, aovs.height);
+    let mut output = Vec::with_capacity(input.len());
+    for y in 0..height {
+        for x in 0..width {
+            let p = (y * width + x) as usize;
+            let mut sum = Vec3(0.0, 0.0, 0.0);
+            let mut weight_sum = 0.0;
+            for (ky, hy) in KERNEL.iter().enumerate() {
+                for (kx, hx) in KERNEL.iter().enumerate() {
+                    let qx = x + (kx as i32 - 2) * step;
+                    let qy = y + (ky as i32 - 2) * step;
+                    if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                        continue;
+                    }
+                    let q = (qy * width + qx) as usize;
+
+                    let dc = (input[p] - input[q]).length_squared();
+                    let dn = (aovs.normal[p] - aovs.normal[q]).length_squared();
+                    let da = (aovs.albedo[p] - aovs.albedo[q]).length_squared();
+                    let dz = (aovs.depth[p] - aovs.depth[q]).abs()
+                        / (settings.sigma_depth * aovs.depth[p].max(1e-3));
+                    let w = hx
+                        * hy
+                        * (-dc / (sigma_color * sigma_color)).exp()
+                        * (-dn / (settings.sigma_normal * settings.sigma_normal)).exp()
+                        * (-da / (settings.sigma_albedo * settings.sigma_albedo)).exp()
+                        * (-dz).exp();
+                    sum += w * input[q];
+                    weight_sum += w;
+                }
+            }
+            // The center tap always has a positive weight.
+            output.push(sum / weight_sum);
+        }
+    }
+    output
+}
@@ -0,0 +1,93 @@
+use crate::{
+    aabb::Aabb,
+    camera::PI,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    vec3::*,
+};
+use std::rc::Rc;
+
+/// A flat disk facing +Y, or an annulus when it has a hole in the middle.
+/// Like the other analytic shapes it's placed upright, `Transformed` can tilt
+/// it.
+pub struct Disk {
+    pub center: Point3,
+    pub radius: f64,
+    /// Radius of the hole, zero for a full disk.
+    pub inner_radius: f64,
+    pub mat_ptr: Rc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(center: Point3, radius: f64, m: &Rc<dyn Material>) -> Self {
+        Disk::annulus(center, 0.0, radius, m)
+    }
+
+    /// A ring between `inner_radius` and `radius`, never hit when the hole
+    /// is as wide as the disk.
+    pub fn annulus(center: Point3, inner_radius: f64, radius: f64, m: &Rc<dyn Material>) -> Self {
+        Disk {
+            center,
+            radius,
+            inner_radius,
+            mat_ptr: m.clone(),
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let o = r.orig() - self.center;
+        let d = r.dir();
+        // A ring with no width, such as the cap of a zero radius cylinder,
+        // has no area to hit.
+        if self.inner_radius >= self.radius || d.y() == 0.0 {
+            return None;
+        }
+        let t = -o.y() / d.y();
+        if t < t_min || t_max < t {
+            return None;
+        }
+        let q = o + t * d;
+        let dist2 = q.x() * q.x() + q.z() * q.z();
+        if dist2 > self.radius * self.radius || dist2 < self.inner_radius * self.inner_radius {
+            return None;
+        }
+
+        let mut rec = HitRecord {
+            t,
+            p: r.at(t),
+            ..Default::default()
+        };
+        rec.set_normal_face(r, Vec3(0.0, 1.0, 0.0));
+        // u goes around the center like on a sphere, v outwards across the ring.
+        let dist = dist2.sqrt();
+        let width = self.radius - self.inner_radius;
+        rec.u = (f64::atan2(-q.z(), q.x()) + PI) / (2.0 * PI);
+        rec.v = (dist - self.inner_radius) / width;
+        rec.dpdu = 2.0 * PI * Vec3(q.z(), 0.0, -q.x());
+        rec.dpdv = if dist > 0.0 {
+            width / dist * Vec3(q.x(), 0.0, q.z())
+        } else {
+            Vec3(width, 0.0, 0.0)
+        };
+        rec.mat_ptr = Some(self.mat_ptr.clone());
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        // Padded so the box isn't flat.
+        Some(Aabb::around(
+            self.center,
+            Vec3(self.radius, 1e-4, self.radius),
+        ))
+    }
+}
+
+impl std::convert::From<Disk> for Rc<dyn Hittable> {
+    fn from(disk: Disk) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(disk);
+        trait_object
+    }
+}
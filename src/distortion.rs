@@ -0,0 +1,165 @@
+use crate::camera::Camera;
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::str::FromStr;
+
+/// Brown-Conrady lens distortion, with the radial (k) and tangential (p)
+/// coefficients calibration tools like OpenCV report. Works on normalized
+/// image coordinates, the image plane one unit in front of the lens.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BrownConrady {
+    pub k1: f64,
+    pub k2: f64,
+    pub k3: f64,
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl FromStr for BrownConrady {
+    type Err = String;
+
+    /// Parses `k1,k2,p1,p2[,k3]`, the order OpenCV lists them in.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_coefficients(s)?[..] {
+            [k1, k2, p1, p2] => Ok(BrownConrady {
+                k1,
+                k2,
+                k3: 0.0,
+                p1,
+                p2,
+            }),
+            [k1, k2, p1, p2, k3] => Ok(BrownConrady { k1, k2, k3, p1, p2 }),
+            _ => Err("expected k1,k2,p1,p2[,k3]".to_string()),
+        }
+    }
+}
+
+impl BrownConrady {
+    /// The ideal pinhole image point that the lens moves to `(xd, yd)`.
+    ///
+    /// The lens maps (x, y) to `(x, y) * (1 + k1 r² + k2 r⁴ + k3 r⁶)` plus the
+    /// tangential terms. This inverts that by fixed point iteration, like
+    /// OpenCV's `undistortPoints`, which converges for sensible calibrations.
+    pub fn undistort(&self, (xd, yd): (f64, f64)) -> (f64, f64) {
+        let (mut x, mut y) = (xd, yd);
+        for _ in 0..20 {
+            let r2 = x * x + y * y;
+            let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+            let dx = 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+            let dy = self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+            x = (xd - dx) / radial;
+            y = (yd - dy) / radial;
+        }
+        (x, y)
+    }
+}
+
+/// Parses a comma separated list of numbers.
+pub fn parse_coefficients(s: &str) -> Result<Vec<f64>, String> {
+    s.split(',')
+        .map(|c| c.trim().parse().map_err(|e| format!("{}: {}", c, e)))
+        .collect()
+}
+
+/// Lens imperfections applied on top of an ideal camera, as cheap stand-ins
+/// for tracing through real lenses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LensEffects {
+    pub distortion: BrownConrady,
+    /// Magnification of the red and blue images relative to the green one,
+    /// for lateral chromatic aberration. One when there's none.
+    pub chromatic_aberration: (f64, f64),
+    /// Coefficients of r², r⁴ and r⁶ in the light falloff `1 + a1 r² + ...`,
+    /// with r the distance from the center in normalized image coordinates.
+    pub vignetting: [f64; 3],
+}
+
+impl Default for LensEffects {
+    fn default() -> Self {
+        LensEffects {
+            distortion: BrownConrady::default(),
+            chromatic_aberration: (1.0, 1.0),
+            vignetting: [0.0; 3],
+        }
+    }
+}
+
+/* ========================================== */
+/// Wraps a camera with `LensEffects`.
+pub struct DistortedCamera {
+    inner: Box<dyn Camera>,
+    effects: LensEffects,
+    /// Half the width and height of the frame in normalized image coordinates.
+    half_size: (f64, f64),
+}
+
+impl DistortedCamera {
+    /// `half_height` is the tangent of half the vertical field of view of the
+    /// inner camera.
+    pub fn new(
+        inner: Box<dyn Camera>,
+        effects: LensEffects,
+        half_height: f64,
+        aspect_ratio: f64,
+    ) -> Self {
+        DistortedCamera {
+            inner,
+            effects,
+            half_size: (half_height * aspect_ratio, half_height),
+        }
+    }
+
+    #[inline]
+    fn normalized(&self, s: f64, t: f64) -> (f64, f64) {
+        (
+            (2.0 * s - 1.0) * self.half_size.0,
+            (2.0 * t - 1.0) * self.half_size.1,
+        )
+    }
+
+    /// The ray of the inner camera that lands on normalized position `p` of
+    /// the distorted image, whose magnification is `scale`.
+    fn ray_at(&self, (x, y): (f64, f64), scale: f64, lens: (f64, f64)) -> Option<Ray> {
+        let (x, y) = self.effects.distortion.undistort((x / scale, y / scale));
+        let s = 0.5 * (x / self.half_size.0 + 1.0);
+        let t = 0.5 * (y / self.half_size.1 + 1.0);
+        self.inner.generate_ray(s, t, lens)
+    }
+}
+
+impl Camera for DistortedCamera {
+    fn generate_ray(&self, s: f64, t: f64, lens: (f64, f64)) -> Option<Ray> {
+        self.ray_at(self.normalized(s, t), 1.0, lens)
+    }
+
+    fn generate_channel_ray(
+        &self,
+        s: f64,
+        t: f64,
+        lens: (f64, f64),
+        channel: usize,
+    ) -> Option<Ray> {
+        let (red, blue) = self.effects.chromatic_aberration;
+        let scale = match channel {
+            0 => red,
+            2 => blue,
+            _ => 1.0,
+        };
+        self.ray_at(self.normalized(s, t), scale, lens)
+    }
+
+    fn is_chromatic(&self) -> bool {
+        self.effects.chromatic_aberration != (1.0, 1.0)
+    }
+
+    fn vignetting(&self, s: f64, t: f64) -> f64 {
+        let (x, y) = self.normalized(s, t);
+        let r2 = x * x + y * y;
+        let [a1, a2, a3] = self.effects.vignetting;
+        (1.0 + r2 * (a1 + r2 * (a2 + r2 * a3))).max(0.0)
+    }
+
+    fn depth(&self, p: Point3) -> f64 {
+        self.inner.depth(p)
+    }
+}
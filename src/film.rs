@@ -0,0 +1,340 @@
+use crate::aov::AovBuffers;
+use crate::checkpoint::{read_f64, read_u32};
+use crate::color::Color;
+use crate::filter::Filter;
+use crate::tonemap::DisplayTransform;
+use crate::vec3::Vec3;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The filtered color of one pixel, along with running statistics of the
+/// samples taken in it.
+#[derive(Clone, Copy)]
+pub struct PixelStats {
+    /// Sum of the filter weighted samples splatted onto the pixel.
+    pub weighted_sum: Color,
+    pub weight_sum: f64,
+    /// Samples taken in the pixel itself.
+    pub count: u32,
+    /// Welford's running mean and sum of squared deviations of the luminance.
+    mean: f64,
+    m2: f64,
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        PixelStats {
+            weighted_sum: Vec3(0.0, 0.0, 0.0),
+            weight_sum: 0.0,
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+}
+
+impl PixelStats {
+    /// Records a sample taken in the pixel, for the error estimate.
+    pub fn add_sample(&mut self, color: Color) {
+        self.count += 1;
+        let y = luminance(color);
+        let delta = y - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (y - self.mean);
+    }
+
+    #[inline]
+    pub fn splat(&mut self, color: Color, weight: f64) {
+        self.weighted_sum += weight * color;
+        self.weight_sum += weight;
+    }
+
+    pub fn color(&self) -> Color {
+        // Filters with negative lobes can leave almost no weight behind.
+        if self.weight_sum.abs() < 1e-8 {
+            Vec3(0.0, 0.0, 0.0)
+        } else {
+            self.weighted_sum / self.weight_sum
+        }
+    }
+
+    /// Standard error of the mean luminance, relative to the mean. The mean
+    /// is offset a little so near black pixels don't look infinitely noisy.
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        (variance / self.count as f64).sqrt() / (self.mean + 0.01)
+    }
+}
+
+#[inline]
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2
+}
+
+/// A rectangle of pixels, measured from the top left corner of the image
+/// like in image editors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CropWindow {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl FromStr for CropWindow {
+    type Err = String;
+
+    /// Parses `x,y,width,height`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse().map_err(|e| format!("{}: {}", v, e)))
+            .collect::<Result<Vec<i32>, String>>()?;
+        match values[..] {
+            [x, y, width, height] if x >= 0 && y >= 0 && width > 0 && height > 0 => {
+                Ok(CropWindow {
+                    x,
+                    y,
+                    width,
+                    height,
+                })
+            }
+            [_, _, _, _] => Err("expected a non-negative corner and a positive size".to_string()),
+            _ => Err("expected x,y,width,height".to_string()),
+        }
+    }
+}
+
+impl CropWindow {
+    /// Whether the window lies within an image of the given size.
+    pub fn fits(&self, width: i32, height: i32) -> bool {
+        let right = self.x.checked_add(self.width);
+        let bottom = self.y.checked_add(self.height);
+        right.is_some_and(|right| right <= width) && bottom.is_some_and(|bottom| bottom <= height)
+    }
+
+    /// The columns and rows the window covers in a film of height `height`,
+    /// whose rows count up from the bottom.
+    pub fn film_ranges(&self, height: i32) -> (Range<i32>, Range<i32>) {
+        let bottom = height - self.y - self.height;
+        (self.x..self.x + self.width, bottom..bottom + self.height)
+    }
+}
+
+/// The pixels of a `width` wide row major buffer inside `columns` and `rows`.
+pub fn crop_buffer<T: Clone>(
+    data: &[T],
+    width: i32,
+    (columns, rows): (Range<i32>, Range<i32>),
+) -> Vec<T> {
+    rows.flat_map(|j| {
+        let start = (j * width + columns.start) as usize;
+        data[start..start + columns.len()].iter().cloned()
+    })
+    .collect()
+}
+
+/// The framebuffer samples are accumulated into. Pixel (0, 0) is the bottom
+/// left corner, matching the camera's (u, v).
+#[derive(Clone)]
+pub struct Film {
+    pub width: i32,
+    pub height: i32,
+    pixels: Vec<PixelStats>,
+    /// Auxiliary buffers, filled when enabled. Checkpoints keep them too.
+    pub aovs: Option<AovBuffers>,
+}
+
+impl Film {
+    pub fn new(width: i32, height: i32) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![PixelStats::default(); (width * height) as usize],
+            aovs: None,
+        }
+    }
+
+    /// A film holding only the pixels inside `window`.
+    pub fn crop(&self, window: &CropWindow) -> Film {
+        let ranges = window.film_ranges(self.height);
+        Film {
+            width: window.width,
+            height: window.height,
+            pixels: crop_buffer(&self.pixels, self.width, ranges.clone()),
+            aovs: self.aovs.as_ref().map(|aovs| aovs.crop(ranges)),
+        }
+    }
+
+    pub fn enable_aovs(&mut self) {
+        if self.aovs.is_none() {
+            self.aovs = Some(AovBuffers::new(self.width, self.height));
+        }
+    }
+
+    #[inline]
+    pub fn pixel(&self, x: i32, y: i32) -> &PixelStats {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    #[inline]
+    pub fn pixel_mut(&mut self, x: i32, y: i32) -> &mut PixelStats {
+        &mut self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Overwrites the reconstructed color of a pixel, e.g. after denoising.
+    pub fn set_color(&mut self, x: i32, y: i32, color: Color) {
+        let pixel = self.pixel_mut(x, y);
+        pixel.weighted_sum = color;
+        pixel.weight_sum = 1.0;
+    }
+
+    /// Adds a sample taken at offset (dx, dy) inside pixel (x, y), splatting
+    /// it to all the pixels the filter reaches inside `(columns, rows)`.
+    pub fn add_sample(
+        &mut self,
+        x: i32,
+        y: i32,
+        (dx, dy): (f64, f64),
+        color: Color,
+        filter: &dyn Filter,
+        (columns, rows): (Range<i32>, Range<i32>),
+    ) {
+        self.pixel_mut(x, y).add_sample(color);
+
+        let (px, py) = (x as f64 + dx, y as f64 + dy);
+        let r = filter.radius();
+        let x0 = ((px - 0.5 - r).ceil() as i32).max(columns.start);
+        let x1 = ((px - 0.5 + r).floor() as i32).min(columns.end - 1);
+        let y0 = ((py - 0.5 - r).ceil() as i32).max(rows.start);
+        let y1 = ((py - 0.5 + r).floor() as i32).min(rows.end - 1);
+        for sy in y0..=y1 {
+            for sx in x0..=x1 {
+                let weight = filter.evaluate(sx as f64 + 0.5 - px, sy as f64 + 0.5 - py);
+                if weight != 0.0 {
+                    self.pixel_mut(sx, sy).splat(color, weight);
+                }
+            }
+        }
+    }
+
+    /// Writes the image as an ASCII PPM, top row first.
+    pub fn write_ppm(&self, out: &mut impl Write, display: &DisplayTransform) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                crate::color::write_color(out, self.pixel(x, y).color(), 1, display)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the image as a PNG.
+    pub fn write_png(&self, out: &mut impl Write, display: &DisplayTransform) -> io::Result<()> {
+        let mut rgb = Vec::with_capacity(self.pixels.len() * 3);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                rgb.extend_from_slice(&crate::color::display_bytes(
+                    self.pixel(x, y).color(),
+                    display,
+                ));
+            }
+        }
+        crate::png::write_png(out, self.width as u32, self.height as u32, &rgb)
+    }
+
+    /// Saves the image, as a PNG when `path` ends in `.png` and as a PPM
+    /// otherwise. It's written next to `path` first and then renamed, so
+    /// viewers polling the file never see half an image.
+    pub fn save(&self, path: impl AsRef<Path>, display: &DisplayTransform) -> io::Result<()> {
+        let path = path.as_ref();
+        let png = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut out = io::BufWriter::new(std::fs::File::create(&tmp)?);
+        if png {
+            self.write_png(&mut out, display)?;
+        } else {
+            self.write_ppm(&mut out, display)?;
+        }
+        out.flush()?;
+        drop(out);
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Writes the accumulated samples losslessly, with the AOVs if there are
+    /// any, for checkpoints.
+    pub fn write_raw(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&(self.width as u32).to_le_bytes())?;
+        out.write_all(&(self.height as u32).to_le_bytes())?;
+        for p in &self.pixels {
+            let sum = p.weighted_sum;
+            for x in [sum.0, sum.1, sum.2, p.weight_sum, p.mean, p.m2].iter() {
+                out.write_all(&x.to_le_bytes())?;
+            }
+            out.write_all(&p.count.to_le_bytes())?;
+        }
+        match self.aovs {
+            Some(ref aovs) => {
+                out.write_all(&1u32.to_le_bytes())?;
+                aovs.write_raw(out)
+            }
+            None => out.write_all(&0u32.to_le_bytes()),
+        }
+    }
+
+    pub fn read_raw(input: &mut impl Read) -> io::Result<Film> {
+        // Six f64s and a u32 per pixel.
+        const PIXEL_BYTES: u64 = 6 * 8 + 4;
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let width = read_u32(input)?;
+        let height = read_u32(input)?;
+        let size = (width as i32)
+            .checked_mul(height as i32)
+            .filter(|_| width <= i32::MAX as u32 && height <= i32::MAX as u32)
+            .ok_or_else(|| invalid("Film size out of range"))?;
+
+        // Read the payload before allocating the film, so a corrupt size
+        // can't ask for more memory than the file holds.
+        let expected = size as u64 * PIXEL_BYTES;
+        let mut payload = Vec::new();
+        input.take(expected).read_to_end(&mut payload)?;
+        if payload.len() as u64 != expected {
+            return Err(invalid("Truncated film data"));
+        }
+
+        let mut film = Film::new(width as i32, height as i32);
+        let payload = &mut payload.as_slice();
+        for p in film.pixels.iter_mut() {
+            p.weighted_sum = Vec3(read_f64(payload)?, read_f64(payload)?, read_f64(payload)?);
+            p.weight_sum = read_f64(payload)?;
+            p.mean = read_f64(payload)?;
+            p.m2 = read_f64(payload)?;
+            p.count = read_u32(payload)?;
+        }
+        if read_u32(input)? != 0 {
+            film.aovs = Some(AovBuffers::read_raw(film.width, film.height, input)?);
+        }
+        Ok(film)
+    }
+
+    /// Writes the number of samples taken per pixel as a grayscale PPM,
+    /// white being `max_samples`.
+    pub fn write_sample_count_ppm(&self, out: &mut impl Write, max_samples: u32) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let level = (255 * self.pixel(x, y).count.min(max_samples)) / max_samples.max(1);
+                writeln!(out, "{0} {0} {0}", level)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use crate::camera::PI;
+use std::str::FromStr;
+
+/// A pixel reconstruction filter. Each sample is splatted to every pixel
+/// whose center lies within `radius` of it, weighted by `evaluate`.
+pub trait Filter {
+    fn radius(&self) -> f64;
+    /// Weight of a sample offset by (dx, dy) pixels from a pixel center.
+    fn evaluate(&self, dx: f64, dy: f64) -> f64;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(FilterKind::Box),
+            "tent" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            _ => Err(format!("Unknown filter: {}", s)),
+        }
+    }
+}
+
+impl FilterKind {
+    /// The radius each filter is usually run with.
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+
+    pub fn build(self, radius: Option<f64>) -> Box<dyn Filter> {
+        let radius = radius.unwrap_or_else(|| self.default_radius());
+        match self {
+            FilterKind::Box => Box::new(BoxFilter::new(radius)),
+            FilterKind::Tent => Box::new(TentFilter::new(radius)),
+            FilterKind::Gaussian => Box::new(GaussianFilter::new(radius, 2.0)),
+            FilterKind::Mitchell => Box::new(MitchellFilter::new(radius, 1.0 / 3.0, 1.0 / 3.0)),
+            FilterKind::Lanczos => Box::new(LanczosFilter::new(radius, 3.0)),
+        }
+    }
+}
+
+/* ========================================== */
+/// Equal weight over a square, which with radius 0.5 just sums the samples
+/// of each pixel.
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> Self {
+        BoxFilter { radius }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        if dx.abs() <= self.radius && dy.abs() <= self.radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/* ========================================== */
+/// Linear falloff to zero at the radius, separately in x and y.
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> Self {
+        TentFilter { radius }
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        (self.radius - dx.abs()).max(0.0) * (self.radius - dy.abs()).max(0.0)
+    }
+}
+
+/* ========================================== */
+/// A Gaussian of falloff `alpha`, shifted down to reach zero at the radius.
+pub struct GaussianFilter {
+    radius: f64,
+    alpha: f64,
+    exp_at_radius: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, alpha: f64) -> Self {
+        GaussianFilter {
+            radius,
+            alpha,
+            exp_at_radius: (-alpha * radius * radius).exp(),
+        }
+    }
+
+    #[inline]
+    fn gaussian(&self, d: f64) -> f64 {
+        ((-self.alpha * d * d).exp() - self.exp_at_radius).max(0.0)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.gaussian(dx) * self.gaussian(dy)
+    }
+}
+
+/* ========================================== */
+/// The Mitchell-Netravali cubic with parameters B and C, which trades
+/// blurring against ringing. B = C = 1/3 is the authors' recommendation.
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> Self {
+        MitchellFilter { radius, b, c }
+    }
+
+    /// The cubic over [-2, 2], evaluated at `x` scaled from [-radius, radius].
+    fn mitchell_1d(&self, x: f64) -> f64 {
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+        let v = if x > 2.0 {
+            0.0
+        } else if x > 1.0 {
+            (-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c)
+        } else {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b)
+        };
+        v / 6.0
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.mitchell_1d(dx) * self.mitchell_1d(dy)
+    }
+}
+
+/* ========================================== */
+/// A sinc windowed by a wider sinc, `tau` lobes across the radius.
+pub struct LanczosFilter {
+    radius: f64,
+    tau: f64,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64, tau: f64) -> Self {
+        LanczosFilter { radius, tau }
+    }
+
+    #[inline]
+    fn sinc(x: f64) -> f64 {
+        if x.abs() < 1e-5 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        }
+    }
+
+    fn windowed_sinc(&self, d: f64) -> f64 {
+        let x = d.abs() / self.radius;
+        if x > 1.0 {
+            return 0.0;
+        }
+        LanczosFilter::sinc(x * self.tau) * LanczosFilter::sinc(x)
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.windowed_sinc(dx) * self.windowed_sinc(dy)
+    }
+}
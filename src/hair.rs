@@ -0,0 +1,346 @@
+use crate::{
+    camera::{deg_to_rad, PI},
+    color::Color,
+    hittable::HitRecord,
+    material::Material,
+    ray::Ray,
+    vec3::*,
+};
+use std::rc::Rc;
+
+/// Number of lobes followed explicitly: reflection, transmission through the
+/// fiber, and one internal reflection. Everything after that is lumped into
+/// a last, isotropic lobe.
+const P_MAX: usize = 3;
+
+/// Absorption coefficients of the two kinds of melanin, per unit of
+/// concentration.
+const EUMELANIN: Color = Vec3(0.419, 0.697, 1.37);
+const PHEOMELANIN: Color = Vec3(0.187, 0.4, 1.05);
+
+/// Scattering from hair and fur fibers, after the model by d'Eon et al. and
+/// Chiang et al. as written up in pbrt: light reflects off the cuticle,
+/// or goes through the fiber and out, possibly after bouncing inside it,
+/// being absorbed along the way.
+///
+/// Meant for `Curve`s facing the ray: the BSDF works out where across the
+/// fiber it was hit from `v`, and its orientation from `dpdu`.
+pub struct Hair {
+    /// Absorption inside the fiber, per unit of its diameter.
+    sigma_a: Color,
+    eta: f64,
+    /// Longitudinal variance of each lobe.
+    v: [f64; P_MAX + 1],
+    /// Azimuthal logistic scale.
+    s: f64,
+    /// sin and cos of the scale tilts 2^k α.
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl Hair {
+    /// `beta_m` and `beta_n` are the longitudinal and azimuthal roughness in
+    /// [0, 1], `alpha` the tilt of the cuticle scales in degrees, and `eta`
+    /// the index of refraction of the fiber.
+    pub fn new(sigma_a: Color, beta_m: f64, beta_n: f64, alpha: f64, eta: f64) -> Self {
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let v = [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0];
+        let s =
+            (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [deg_to_rad(alpha).sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [safe_sqrt(1.0 - sin_2k_alpha[0].powi(2)), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        Hair {
+            sigma_a,
+            eta,
+            v,
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    /// Hair coloured by its pigments, from the concentrations of eumelanin,
+    /// which goes from blonde around 0.3 to black around 8, and of the
+    /// reddish pheomelanin.
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64, beta_m: f64, beta_n: f64) -> Self {
+        let sigma_a = eumelanin * EUMELANIN + pheomelanin * PHEOMELANIN;
+        Hair::new(sigma_a, beta_m, beta_n, 2.0, 1.55)
+    }
+
+    /// Hair whose overall colour is about `color`.
+    pub fn from_color(color: Color, beta_m: f64, beta_n: f64) -> Self {
+        let d = Hair::absorption_scale(beta_n);
+        let sigma_a = |c: f64| (c.max(1e-4).ln() / d).powi(2);
+        Hair::new(
+            Vec3(sigma_a(color.0), sigma_a(color.1), sigma_a(color.2)),
+            beta_m,
+            beta_n,
+            2.0,
+            1.55,
+        )
+    }
+
+    /// Relates absorption to the resulting colour, fitted by Chiang et al.
+    fn absorption_scale(beta_n: f64) -> f64 {
+        5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5)
+    }
+
+    /// sin and cos of the outgoing angle tilted by the cuticle scales, for
+    /// lobe `p`.
+    fn tilt(&self, p: usize, sin_theta: f64, cos_theta: f64) -> (f64, f64) {
+        let (sin_alpha, cos_alpha) = match p {
+            0 => (-self.sin_2k_alpha[1], self.cos_2k_alpha[1]),
+            1 => (self.sin_2k_alpha[0], self.cos_2k_alpha[0]),
+            2 => (self.sin_2k_alpha[2], self.cos_2k_alpha[2]),
+            _ => return (sin_theta, cos_theta),
+        };
+        (
+            sin_theta * cos_alpha + cos_theta * sin_alpha,
+            (cos_theta * cos_alpha - sin_theta * sin_alpha).abs(),
+        )
+    }
+
+    /// Attenuation of each lobe for light leaving at `theta_o`, `h` across
+    /// the fiber, and the refracted angle inside it.
+    fn attenuation(&self, sin_theta_o: f64, cos_theta_o: f64, h: f64) -> ([Color; P_MAX + 1], f64) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let etap = safe_sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o;
+        let sin_gamma_t = h / etap;
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let gamma_t = sin_gamma_t.clamp(-1.0, 1.0).asin();
+
+        // Transmittance of one pass through the fiber.
+        let path = 2.0 * cos_gamma_t / cos_theta_t;
+        let transmittance = Vec3(
+            (-self.sigma_a.0 * path).exp(),
+            (-self.sigma_a.1 * path).exp(),
+            (-self.sigma_a.2 * path).exp(),
+        );
+
+        let cos_gamma_o = safe_sqrt(1.0 - h * h);
+        let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let white = Vec3(1.0, 1.0, 1.0);
+        let mut ap = [
+            Vec3(f, f, f),
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, 0.0),
+            white,
+        ];
+        ap[1] = (1.0 - f).powi(2) * transmittance;
+        ap[2] = f * ap[1] * transmittance;
+        let tf = f * transmittance;
+        let rest = Vec3(
+            tf.0 / (1.0 - tf.0),
+            tf.1 / (1.0 - tf.1),
+            tf.2 / (1.0 - tf.2),
+        );
+        ap[3] = ap[2] * rest;
+        (ap, gamma_t)
+    }
+
+    /// Probability of sampling each lobe, by how much light it carries.
+    fn lobe_pdf(ap: &[Color; P_MAX + 1]) -> [f64; P_MAX + 1] {
+        let y = |c: Color| 0.212671 * c.0 + 0.715160 * c.1 + 0.072169 * c.2;
+        let total: f64 = ap.iter().map(|&c| y(c)).sum();
+        let mut pdf = [0.0; P_MAX + 1];
+        for (p, &c) in ap.iter().enumerate() {
+            pdf[p] = if total > 0.0 { y(c) / total } else { 0.25 };
+        }
+        pdf
+    }
+}
+
+impl Material for Hair {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, attenuation: &mut Color) -> Option<Ray> {
+        // Along the fiber, across it, and out of the strip.
+        let ss = rec.dpdu.unit_vector();
+        let ns = rec.dpdu.cross(rec.dpdv).unit_vector();
+        let ts = ns.cross(ss);
+        let wo = -ray_in.dir().unit_vector();
+        let wo = Vec3(wo.dot(&ss), wo.dot(&ts), wo.dot(&ns));
+
+        let h = -1.0 + 2.0 * rec.v;
+        let gamma_o = h.clamp(-1.0, 1.0).asin();
+        let sin_theta_o = wo.0;
+        let cos_theta_o = safe_sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let phi_o = wo.2.atan2(wo.1);
+
+        let (ap, gamma_t) = self.attenuation(sin_theta_o, cos_theta_o, h);
+        let lobe_pdf = Hair::lobe_pdf(&ap);
+
+        // Pick a lobe, then the longitudinal and azimuthal angles in it.
+        let mut choice = random_f64();
+        let mut p = P_MAX;
+        for (lobe, &pdf) in lobe_pdf.iter().enumerate().take(P_MAX) {
+            if choice < pdf {
+                p = lobe;
+                break;
+            }
+            choice -= pdf;
+        }
+        let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let u = random_f64().max(1e-5);
+        let v = self.v[p];
+        let cos_theta = 1.0 + v * (u + (1.0 - u) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * random_f64()).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+        let dphi = if p < P_MAX {
+            phi(p, gamma_o, gamma_t) + sample_trimmed_logistic(random_f64(), self.s)
+        } else {
+            2.0 * PI * random_f64()
+        };
+        let phi_i = phi_o + dphi;
+
+        // Sum up every lobe's contribution to the chosen direction, and the
+        // probability of having chosen it through any of them.
+        let mut f = Vec3(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+        for (lobe, &a) in ap.iter().enumerate() {
+            let (sin_theta_op, cos_theta_op) = self.tilt(lobe, sin_theta_o, cos_theta_o);
+            let m = mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[lobe],
+            );
+            let n = if lobe < P_MAX {
+                np(dphi, lobe, self.s, gamma_o, gamma_t)
+            } else {
+                1.0 / (2.0 * PI)
+            };
+            f += m * n * a;
+            pdf += m * n * lobe_pdf[lobe];
+        }
+        if pdf <= 0.0 {
+            return None;
+        }
+        // The BSDF carries no cosine term: it's divided out of f.
+        *attenuation = f / pdf;
+
+        let wi = sin_theta_i * ss + cos_theta_i * phi_i.cos() * ts + cos_theta_i * phi_i.sin() * ns;
+        Some(Ray::new(rec.p, wi))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        // The colour from_color would have been given, about.
+        let d = Hair::absorption_scale(0.3);
+        let c = |sigma_a: f64| (-sigma_a.sqrt() * d).exp();
+        Vec3(c(self.sigma_a.0), c(self.sigma_a.1), c(self.sigma_a.2))
+    }
+}
+
+/* ========================================== */
+#[inline]
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+/// Fresnel reflectance going from air into a dielectric of index `eta`.
+fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(-1.0, 1.0).abs();
+    let sin_theta_t = safe_sqrt(1.0 - cos_theta_i * cos_theta_i) / eta;
+    if sin_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Modified Bessel function of the first kind, of order zero.
+fn i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+/// Longitudinal scattering with variance `v`.
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    // Low variances overflow the direct form.
+    if v <= 0.1 {
+        (log_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+/// Azimuthal angle light leaves at through lobe `p`.
+#[inline]
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    (-x / s).exp() / (s * (1.0 + (-x / s).exp()).powi(2))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+/// The logistic distribution with scale `s`, limited to [-π, π].
+fn trimmed_logistic(x: f64, s: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64) -> f64 {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    x.clamp(-PI, PI)
+}
+
+/// Azimuthal scattering of lobe `p`, for a difference of `phi_diff` between the
+/// incoming and outgoing azimuths.
+fn np(phi_diff: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi_diff - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s)
+}
+
+impl From<Hair> for Rc<dyn Material> {
+    #[inline]
+    fn from(material: Hair) -> Self {
+        let trait_object: Rc<dyn Material> = Rc::new(material);
+        trait_object
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    aabb::Aabb,
+    film::luminance,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    texture::{ImageTexture, Texture},
+    vec3::*,
+};
+use std::rc::Rc;
+
+/// Terrain given by heights sampled on a regular grid, each cell split into
+/// two triangles. Rays walk the grid cell by cell (a 2D DDA), testing the
+/// triangles only in cells whose height range they pass through, so large
+/// grids cost little more than small ones.
+pub struct Heightfield {
+    /// Corner at the lowest x and z, at height zero.
+    origin: Point3,
+    /// Spacing of the samples along x and z.
+    spacing: (f64, f64),
+    /// Number of samples along x and z.
+    resolution: (usize, usize),
+    /// Heights above the origin, row by row along x, starting at the lowest z.
+    heights: Vec<f64>,
+    /// Normals at the samples, interpolated across the triangles.
+    normals: Vec<Vec3>,
+    /// Lowest and highest height of each cell.
+    cell_ranges: Vec<(f64, f64)>,
+    bounds: Aabb,
+    pub mat_ptr: Rc<dyn Material>,
+}
+
+impl Heightfield {
+    /// `samples` holds `resolution.0` by `resolution.1` heights, row by row
+    /// along x starting at the lowest z, spread over `size.x()` by
+    /// `size.z()` and scaled by `size.y()`.
+    pub fn new(
+        samples: Vec<f64>,
+        resolution: (usize, usize),
+        origin: Point3,
+        size: Vec3,
+        m: &Rc<dyn Material>,
+    ) -> Self {
+        let (nx, nz) = resolution;
+        assert!(
+            nx >= 2 && nz >= 2,
+            "A heightfield needs at least 2x2 samples"
+        );
+        assert_eq!(
+            nx * nz,
+            samples.len(),
+            "Heightfield size does not match its samples"
+        );
+        let spacing = (size.x() / (nx - 1) as f64, size.z() / (nz - 1) as f64);
+        let heights: Vec<f64> = samples.iter().map(|h| h * size.y()).collect();
+        let at = |i: usize, j: usize| heights[j * nx + i];
+
+        // Central differences inside, one sided at the edges.
+        let mut normals = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(nx - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(nz - 1));
+                let dhdx = (at(i1, j) - at(i0, j)) / ((i1 - i0) as f64 * spacing.0);
+                let dhdz = (at(i, j1) - at(i, j0)) / ((j1 - j0) as f64 * spacing.1);
+                normals.push(Vec3(-dhdx, 1.0, -dhdz).unit_vector());
+            }
+        }
+
+        let mut cell_ranges = Vec::with_capacity((nx - 1) * (nz - 1));
+        for j in 0..nz - 1 {
+            for i in 0..nx - 1 {
+                let corners = [at(i, j), at(i + 1, j), at(i, j + 1), at(i + 1, j + 1)];
+                let low = corners.iter().copied().fold(f64::INFINITY, f64::min);
+                let high = corners.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                cell_ranges.push((low, high));
+            }
+        }
+
+        let low = heights.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = heights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        // Padded so a flat field's box isn't flat.
+        let bounds = Aabb::new(
+            origin + Vec3(0.0, low - 1e-4, 0.0),
+            origin + Vec3(size.x(), high + 1e-4, size.z()),
+        );
+        Heightfield {
+            origin,
+            spacing,
+            resolution,
+            heights,
+            normals,
+            cell_ranges,
+            bounds,
+            mat_ptr: m.clone(),
+        }
+    }
+
+    /// Heights from the brightness of a grayscale image, one sample per
+    /// pixel, with the top of the image at the lowest z.
+    pub fn from_image(
+        image: &ImageTexture,
+        origin: Point3,
+        size: Vec3,
+        m: &Rc<dyn Material>,
+    ) -> Self {
+        let (width, height) = image.size();
+        let mut samples = Vec::with_capacity(width * height);
+        for j in 0..height {
+            let v = 1.0 - (j as f64 + 0.5) / height as f64;
+            for i in 0..width {
+                let u = (i as f64 + 0.5) / width as f64;
+                samples.push(luminance(image.value(u, v, &origin)));
+            }
+        }
+        Heightfield::new(samples, (width, height), origin, size, m)
+    }
+
+    /// Rolling hills of fractal value noise spanning [0, 1], `resolution` samples
+    /// on a side, with `octaves` layers of detail. Drawn from the scene's
+    /// random numbers, so the seed picks the landscape.
+    pub fn from_noise(
+        resolution: usize,
+        octaves: u32,
+        origin: Point3,
+        size: Vec3,
+        m: &Rc<dyn Material>,
+    ) -> Self {
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let mut samples = vec![0.0; resolution * resolution];
+        for octave in 0..octaves {
+            // Each octave has twice the lattice cells and half the amplitude.
+            let cells = 2usize << octave;
+            let amplitude = 0.5f64.powi(octave as i32);
+            let lattice: Vec<f64> = (0..(cells + 1) * (cells + 1))
+                .map(|_| random_f64())
+                .collect();
+            let scale = cells as f64 / (resolution - 1) as f64;
+            for j in 0..resolution {
+                for i in 0..resolution {
+                    let (x, z) = (i as f64 * scale, j as f64 * scale);
+                    let (ci, cj) = ((x as usize).min(cells - 1), (z as usize).min(cells - 1));
+                    let (fx, fz) = (smooth(x - ci as f64), smooth(z - cj as f64));
+                    let l = |a: usize, b: usize| lattice[(cj + b) * (cells + 1) + ci + a];
+                    let near = l(0, 0) + fx * (l(1, 0) - l(0, 0));
+                    let far = l(0, 1) + fx * (l(1, 1) - l(0, 1));
+                    samples[j * resolution + i] += amplitude * (near + fz * (far - near));
+                }
+            }
+        }
+        // Stretched to fill [0, 1], the octaves mostly average out otherwise.
+        let low = samples.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        for h in samples.iter_mut() {
+            *h = (*h - low) / (high - low).max(1e-12);
+        }
+        Heightfield::new(samples, (resolution, resolution), origin, size, m)
+    }
+
+    #[inline]
+    fn vertex(&self, i: usize, j: usize) -> (Point3, Vec3) {
+        let k = j * self.resolution.0 + i;
+        let p = self.origin
+            + Vec3(
+                i as f64 * self.spacing.0,
+                self.heights[k],
+                j as f64 * self.spacing.1,
+            );
+        (p, self.normals[k])
+    }
+
+    /// Nearest hit with the two triangles of cell (i, j).
+    fn hit_cell(&self, i: usize, j: usize, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let v00 = self.vertex(i, j);
+        let v10 = self.vertex(i + 1, j);
+        let v01 = self.vertex(i, j + 1);
+        let v11 = self.vertex(i + 1, j + 1);
+        let mut nearest: Option<(f64, Vec3, (f64, f64))> = None;
+        for &(a, b, c) in [(v00, v10, v11), (v00, v11, v01)].iter() {
+            let t_max = nearest.map_or(t_max, |(t, _, _)| t);
+            if let Some((t, b1, b2)) = hit_triangle(r, a.0, b.0, c.0, t_min, t_max) {
+                let normal = (1.0 - b1 - b2) * a.1 + b1 * b.1 + b2 * c.1;
+                let p = (1.0 - b1 - b2) * a.0 + b1 * b.0 + b2 * c.0;
+                nearest = Some((t, normal, (p.x(), p.z())));
+            }
+        }
+        let (t, normal, (x, z)) = nearest?;
+
+        let mut rec = HitRecord {
+            t,
+            p: r.at(t),
+            ..Default::default()
+        };
+        rec.set_normal_face(r, normal.unit_vector());
+        let size = (
+            self.spacing.0 * (self.resolution.0 - 1) as f64,
+            self.spacing.1 * (self.resolution.1 - 1) as f64,
+        );
+        rec.u = (x - self.origin.x()) / size.0;
+        rec.v = (z - self.origin.z()) / size.1;
+        // Along the ground, tilted by the slope of the cell.
+        let (p00, p10, p01) = (v00.0, v10.0, v01.0);
+        rec.dpdu = (p10 - p00) * (size.0 / self.spacing.0);
+        rec.dpdv = (p01 - p00) * (size.1 / self.spacing.1);
+        rec.mat_ptr = Some(self.mat_ptr.clone());
+        Some(rec)
+    }
+}
+
+/// Möller-Trumbore ray triangle intersection, returning `t` and the
+/// barycentric weights of `b` and `c`.
+fn hit_triangle(
+    r: &Ray,
+    a: Point3,
+    b: Point3,
+    c: Point3,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let (e1, e2) = (b - a, c - a);
+    let pvec = r.dir().cross(e2);
+    let det = e1.dot(&pvec);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = r.orig() - a;
+    let u = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let qvec = tvec.cross(e1);
+    let v = r.dir().dot(&qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(&qvec) * inv_det;
+    if t < t_min || t_max < t {
+        return None;
+    }
+    Some((t, u, v))
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t_enter, t_exit) = self.bounds.hit(r, t_min, t_max)?;
+        let (nx, nz) = (self.resolution.0 - 1, self.resolution.1 - 1);
+        let (o, d) = (r.orig(), r.dir());
+
+        // The cell the ray enters the box in, and the t of the next cell
+        // boundary and between boundaries along x and z.
+        let start = r.at(t_enter) - self.origin;
+        let cell =
+            |x: f64, spacing: f64, n: usize| ((x / spacing).floor().max(0.0) as usize).min(n - 1);
+        let (mut i, mut j) = (
+            cell(start.x(), self.spacing.0, nx),
+            cell(start.z(), self.spacing.1, nz),
+        );
+        let axis = |index: usize, spacing: f64, origin: f64, o: f64, d: f64| {
+            if d > 0.0 {
+                let boundary = origin + (index + 1) as f64 * spacing;
+                ((boundary - o) / d, spacing / d)
+            } else if d < 0.0 {
+                let boundary = origin + index as f64 * spacing;
+                ((boundary - o) / d, -spacing / d)
+            } else {
+                (f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (mut next_x, delta_x) = axis(i, self.spacing.0, self.origin.x(), o.x(), d.x());
+        let (mut next_z, delta_z) = axis(j, self.spacing.1, self.origin.z(), o.z(), d.z());
+
+        let mut t = t_enter;
+        loop {
+            let t_next = next_x.min(next_z).min(t_exit);
+            // Skip the triangles when the ray passes above or below the cell.
+            let (low, high) = self.cell_ranges[j * nx + i];
+            let (y0, y1) = (o.y() + t * d.y(), o.y() + t_next * d.y());
+            let (y_low, y_high) = (y0.min(y1) - self.origin.y(), y0.max(y1) - self.origin.y());
+            if y_high >= low - 1e-9 && y_low <= high + 1e-9 {
+                if let Some(rec) = self.hit_cell(i, j, r, t_min, t_max) {
+                    return Some(rec);
+                }
+            }
+            if t_next >= t_exit {
+                return None;
+            }
+            t = t_next;
+            if next_x < next_z {
+                if d.x() > 0.0 && i + 1 < nx {
+                    i += 1;
+                } else if d.x() < 0.0 && i > 0 {
+                    i -= 1;
+                } else {
+                    return None;
+                }
+                next_x += delta_x;
+            } else {
+                if d.z() > 0.0 && j + 1 < nz {
+                    j += 1;
+                } else if d.z() < 0.0 && j > 0 {
+                    j -= 1;
+                } else {
+                    return None;
+                }
+                next_z += delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl std::convert::From<Heightfield> for Rc<dyn Hittable> {
+    fn from(heightfield: Heightfield) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(heightfield);
+        trait_object
+    }
+}
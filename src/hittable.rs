@@ -1,16 +1,30 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::texture::Texture;
 use crate::vec3;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 /// The HitRecord structure, created when a ray hits an object.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct HitRecord {
     pub p: vec3::Point3,
     pub normal: vec3::Vec3,
     pub t: f64,
+    /// Surface coordinates of the hit, used for texture lookups.
+    pub u: f64,
+    pub v: f64,
+    /// Partial derivatives of the surface position, the tangent frame used
+    /// by normal and bump mapping.
+    pub dpdu: vec3::Vec3,
+    pub dpdv: vec3::Vec3,
     pub front_face: bool,
     pub mat_ptr: Option<Rc<dyn Material>>,
+    /// Which object was hit: one plus its index in the innermost
+    /// `HittableList` holding it, zero when not hit through a list.
+    pub object_id: u32,
 }
 
 impl HitRecord {
@@ -28,6 +42,31 @@ impl HitRecord {
 /// Trait which describes if a hit with a ray and returns a HitRecord
 pub trait Hittable {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    /// Every crossing of the surface between `t_min` and `t_max`, nearest
+    /// first. `front_face` tells entries from exits for closed objects, as
+    /// constructive solid geometry needs.
+    fn hit_all(&self, r: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        let mut hits = Vec::new();
+        let mut t_min = t_min;
+        while let Some(rec) = self.hit(r, t_min, t_max) {
+            t_min = step_past(rec.t);
+            hits.push(rec);
+        }
+        hits
+    }
+
+    /// A box holding the object over the time interval, `None` when it's
+    /// unbounded.
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        None
+    }
+}
+
+/// A `t` just past a hit at `t`, to look for the next one from.
+#[inline]
+pub fn step_past(t: f64) -> f64 {
+    t + 1e-6 * t.abs().max(1.0)
 }
 
 pub struct HittableList {
@@ -43,6 +82,16 @@ impl HittableList {
     pub fn add(&mut self, object: &Rc<dyn Hittable>) {
         self.objects.push(object.clone());
     }
+    /// The object with the given ID, as in `HitRecord::object_id`.
+    pub fn get(&self, id: u32) -> Option<&Rc<dyn Hittable>> {
+        self.objects.get((id as usize).checked_sub(1)?)
+    }
+    /// Replaces the object with the given ID. Does nothing if there isn't one.
+    pub fn set(&mut self, id: u32, object: &Rc<dyn Hittable>) {
+        if let Some(slot) = (id as usize).checked_sub(1).and_then(|i| self.objects.get_mut(i)) {
+            *slot = object.clone();
+        }
+    }
 }
 
 impl Hittable for HittableList {
@@ -51,8 +100,11 @@ impl Hittable for HittableList {
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
 
-        for object in &self.objects {
-            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(mut rec) = object.hit(r, t_min, closest_so_far) {
+                    if rec.object_id == 0 {
+                        rec.object_id = index as u32 + 1;
+                    }
                     hit_anything = true;
                     closest_so_far = rec.t;
                     temp_rec = rec;
@@ -65,4 +117,171 @@ impl Hittable for HittableList {
             None
         }
     }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|o| o.bounding_box(time0, time1));
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.surrounding(&b?)))
+    }
+}
+
+/// How an alpha mask decides whether a hit is kept.
+#[derive(Clone, Copy)]
+pub enum AlphaMode {
+    /// Hits with alpha below the threshold are cut out.
+    Threshold(f64),
+    /// Hits are kept with probability alpha, which averages to partial coverage.
+    Stochastic,
+}
+
+/// Wraps a hittable with an opacity mask, for cutouts such as leaves and fences.
+/// Rays pass through the masked parts and carry on to whatever is behind them.
+pub struct AlphaMasked {
+    object: Rc<dyn Hittable>,
+    alpha: Rc<dyn Texture>,
+    mode: AlphaMode,
+}
+
+impl AlphaMasked {
+    pub fn new(object: &Rc<dyn Hittable>, alpha: &Rc<dyn Texture>, mode: AlphaMode) -> Self {
+        AlphaMasked {
+            object: object.clone(),
+            alpha: alpha.clone(),
+            mode,
+        }
+    }
+}
+
+impl Hittable for AlphaMasked {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut t_min = t_min;
+        loop {
+            let rec = self.object.hit(r, t_min, t_max)?;
+            let c = self.alpha.value(rec.u, rec.v, &rec.p);
+            let alpha = (c.0 + c.1 + c.2) / 3.0;
+            let opaque = match self.mode {
+                AlphaMode::Threshold(threshold) => alpha >= threshold,
+                AlphaMode::Stochastic => vec3::random_f64() < alpha,
+            };
+            if opaque {
+                return Some(rec);
+            }
+            // Step just past the cut out hit so it isn't found again.
+            t_min = step_past(rec.t);
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.object.bounding_box(time0, time1)
+    }
+}
+
+impl std::convert::From<AlphaMasked> for Rc<dyn Hittable> {
+    fn from(masked: AlphaMasked) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(masked);
+        trait_object
+    }
+}
+
+/// Places a hittable in the world: scaled by `scale`, rotated by Euler angles
+/// about the X, then Y, then Z axis, and moved by `translation`, all about the
+/// world origin. Optionally tints the materials it's hit with.
+pub struct Transformed {
+    object: Rc<dyn Hittable>,
+    translation: vec3::Vec3,
+    /// Rows of the rotation matrix.
+    rotation: [vec3::Vec3; 3],
+    scale: f64,
+    tint: Option<vec3::Vec3>,
+    /// Tinted versions of the materials hit so far, by the address of the
+    /// material they wrap, so each is made once and keeps its identity.
+    tinted: RefCell<HashMap<usize, Rc<dyn Material>>>,
+}
+
+impl Transformed {
+    /// `rotation` holds the angles about each axis in degrees.
+    pub fn new(
+        object: &Rc<dyn Hittable>,
+        translation: vec3::Vec3,
+        rotation: vec3::Vec3,
+        scale: f64,
+        tint: Option<vec3::Vec3>,
+    ) -> Self {
+        let (sx, cx) = crate::camera::deg_to_rad(rotation.x()).sin_cos();
+        let (sy, cy) = crate::camera::deg_to_rad(rotation.y()).sin_cos();
+        let (sz, cz) = crate::camera::deg_to_rad(rotation.z()).sin_cos();
+        Transformed {
+            object: object.clone(),
+            translation,
+            rotation: [
+                vec3::Vec3(cz * cy, cz * sy * sx - sz * cx, cz * sy * cx + sz * sx),
+                vec3::Vec3(sz * cy, sz * sy * sx + cz * cx, sz * sy * cx - cz * sx),
+                vec3::Vec3(-sy, cy * sx, cy * cx),
+            ],
+            scale,
+            tint,
+            tinted: RefCell::new(HashMap::new()),
+        }
+    }
+
+    #[inline]
+    fn rotate(&self, v: vec3::Vec3) -> vec3::Vec3 {
+        let [a, b, c] = &self.rotation;
+        vec3::Vec3(a.dot(&v), b.dot(&v), c.dot(&v))
+    }
+
+    /// The tinted version of `mat`, made on its first hit.
+    fn tinted(&self, mat: &Rc<dyn Material>, tint: vec3::Vec3) -> Rc<dyn Material> {
+        let key = Rc::as_ptr(mat) as *const () as usize;
+        self.tinted
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| crate::material::Tinted::new(mat, tint).into())
+            .clone()
+    }
+
+    /// Rotation back into object space, by the transposed matrix.
+    #[inline]
+    fn unrotate(&self, v: vec3::Vec3) -> vec3::Vec3 {
+        let [a, b, c] = self.rotation;
+        v.x() * a + v.y() * b + v.z() * c
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // Scaling the direction along with the origin keeps t the same in both spaces.
+        let local = Ray {
+            orig: self.unrotate(r.orig - self.translation) / self.scale,
+            dir: self.unrotate(r.dir) / self.scale,
+            ..*r
+        };
+        let mut rec = self.object.hit(&local, t_min, t_max)?;
+        rec.p = r.at(rec.t);
+        rec.normal = self.rotate(rec.normal);
+        rec.dpdu = self.scale * self.rotate(rec.dpdu);
+        rec.dpdv = self.scale * self.rotate(rec.dpdv);
+        if let Some(tint) = self.tint {
+            rec.mat_ptr = rec.mat_ptr.map(|mat| self.tinted(&mat, tint));
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let corners = self.object.bounding_box(time0, time1)?.corners();
+        let to_world = |c: vec3::Point3| {
+            let p = self.rotate(self.scale * c) + self.translation;
+            Aabb::new(p, p)
+        };
+        Some(corners[1..]
+            .iter()
+            .fold(to_world(corners[0]), |acc, &c| acc.surrounding(&to_world(c))))
+    }
+}
+
+impl std::convert::From<Transformed> for Rc<dyn Hittable> {
+    fn from(transformed: Transformed) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(transformed);
+        trait_object
+    }
 }
@@ -0,0 +1,353 @@
+use crate::camera::{Camera, CameraFrame, CameraParams};
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::io;
+use std::path::Path;
+
+/// One surface of a lens prescription. Lengths are in metres.
+#[derive(Clone, Copy, Debug)]
+pub struct LensInterface {
+    /// Radius of curvature, positive when its center lies towards the film.
+    /// Zero for the aperture stop.
+    pub curvature_radius: f64,
+    /// Distance along the axis to the next interface towards the film.
+    pub thickness: f64,
+    /// Index of refraction of the medium behind the interface.
+    pub ior: f64,
+    pub aperture_radius: f64,
+}
+
+/// A sequence of spherical lens interfaces, from the one facing the scene to
+/// the one facing the film.
+///
+/// Rays are in the camera's space: the film lies in the z = 0 plane and the
+/// lens in front of it, towards positive z.
+#[derive(Clone, Debug)]
+pub struct LensSystem {
+    interfaces: Vec<LensInterface>,
+}
+
+impl LensSystem {
+    /// Loads a prescription file, see `parse`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        LensSystem::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a prescription, one interface per line from the front of the
+    /// lens: curvature radius, thickness, index of refraction and aperture
+    /// diameter, with lengths in millimetres. An IOR of zero stands for air,
+    /// `#` starts a comment.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut interfaces = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid(format!("Malformed lens interface on line {}", number + 1)))?;
+            if values.len() != 4 {
+                return Err(invalid(format!(
+                    "Expected 4 values on line {}, found {}",
+                    number + 1,
+                    values.len()
+                )));
+            }
+            interfaces.push(LensInterface {
+                curvature_radius: values[0] / 1000.0,
+                thickness: values[1] / 1000.0,
+                ior: if values[2] == 0.0 { 1.0 } else { values[2] },
+                aperture_radius: values[3] / 2000.0,
+            });
+        }
+        if interfaces.is_empty() {
+            return Err(invalid("Lens prescription has no interfaces".to_string()));
+        }
+        Ok(LensSystem { interfaces })
+    }
+
+    /// Distance of the front of the lens from the film.
+    pub fn front_z(&self) -> f64 {
+        self.interfaces.iter().map(|i| i.thickness).sum()
+    }
+
+    /// Distance of the rear of the lens from the film.
+    pub fn rear_z(&self) -> f64 {
+        self.rear().thickness
+    }
+
+    pub fn rear(&self) -> &LensInterface {
+        &self.interfaces[self.interfaces.len() - 1]
+    }
+
+    /// Follows a ray leaving the film out through the front of the lens.
+    /// `None` when it's blocked or totally reflected on the way.
+    pub fn trace_from_film(&self, r: &Ray) -> Option<Ray> {
+        // Lens space has the scene towards negative z.
+        let (mut o, mut d) = (flip_z(r.orig), flip_z(r.dir));
+        let mut z = 0.0;
+        for (i, interface) in self.interfaces.iter().enumerate().rev() {
+            z -= interface.thickness;
+            o = interface.intersect(z, o, d)?;
+            if interface.curvature_radius != 0.0 {
+                let normal = interface.normal(z, o, d);
+                let eta_t = if i > 0 {
+                    self.interfaces[i - 1].ior
+                } else {
+                    1.0
+                };
+                d = refract(-d.unit_vector(), normal, interface.ior / eta_t)?;
+            }
+        }
+        Some(Ray::new(flip_z(o), flip_z(d)))
+    }
+
+    /// Follows a ray entering the front of the lens through to the film side.
+    pub fn trace_from_scene(&self, r: &Ray) -> Option<Ray> {
+        let (mut o, mut d) = (flip_z(r.orig), flip_z(r.dir));
+        let mut z = -self.front_z();
+        for (i, interface) in self.interfaces.iter().enumerate() {
+            o = interface.intersect(z, o, d)?;
+            if interface.curvature_radius != 0.0 {
+                let normal = interface.normal(z, o, d);
+                let eta_i = if i > 0 {
+                    self.interfaces[i - 1].ior
+                } else {
+                    1.0
+                };
+                d = refract(-d.unit_vector(), normal, eta_i / interface.ior)?;
+            }
+            z += interface.thickness;
+        }
+        Some(Ray::new(flip_z(o), flip_z(d)))
+    }
+
+    /// Positions of the principal planes and focal points of the thick lens
+    /// approximation, on the scene side and on the film side, as distances
+    /// behind the film. `height` is how far off the axis the rays are traced.
+    fn thick_lens(&self, height: f64) -> Option<([f64; 2], [f64; 2])> {
+        let from_scene = Ray::new(
+            Vec3(height, 0.0, self.front_z() + 1.0),
+            Vec3(0.0, 0.0, -1.0),
+        );
+        let (pz0, fz0) = cardinal_points(&from_scene, &self.trace_from_scene(&from_scene)?);
+        let from_film = Ray::new(Vec3(height, 0.0, self.rear_z() - 1.0), Vec3(0.0, 0.0, 1.0));
+        let (pz1, fz1) = cardinal_points(&from_film, &self.trace_from_film(&from_film)?);
+        Some(([pz0, pz1], [fz0, fz1]))
+    }
+
+    /// Moves the film so that things `focus_dist` in front of it are sharp.
+    /// `None` when the lens can't focus that close.
+    pub fn focus(&mut self, focus_dist: f64, film_diagonal: f64) -> Option<()> {
+        let (pz, fz) = self.thick_lens(0.001 * film_diagonal)?;
+        let f = fz[0] - pz[0];
+        let z = -focus_dist;
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4.0 * f - pz[0]);
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+        // Not a number too when the lens doesn't converge light at all.
+        if !delta.is_finite() {
+            return None;
+        }
+        let last = self.interfaces.len() - 1;
+        self.interfaces[last].thickness += delta;
+        Some(())
+    }
+}
+
+impl LensInterface {
+    /// Where a lens space ray meets the interface, whose vertex is at `z`.
+    /// `None` if it misses or passes outside the aperture.
+    fn intersect(&self, z: f64, o: Vec3, d: Vec3) -> Option<Vec3> {
+        let t = if self.curvature_radius == 0.0 {
+            if d.z() == 0.0 {
+                return None;
+            }
+            (z - o.z()) / d.z()
+        } else {
+            let radius = self.curvature_radius;
+            let oc = o - Vec3(0.0, 0.0, z + radius);
+            let a = d.length_squared();
+            let half_b = oc.dot(&d);
+            let c = oc.length_squared() - radius * radius;
+            let disc = half_b * half_b - a * c;
+            if disc < 0.0 {
+                return None;
+            }
+            let (t0, t1) = ((-half_b - disc.sqrt()) / a, (-half_b + disc.sqrt()) / a);
+            // The side of the sphere the lens uses.
+            if (d.z() > 0.0) ^ (radius < 0.0) {
+                t0.min(t1)
+            } else {
+                t0.max(t1)
+            }
+        };
+        if t <= 0.0 {
+            return None;
+        }
+        let p = o + t * d;
+        if p.x() * p.x() + p.y() * p.y() > self.aperture_radius * self.aperture_radius {
+            return None;
+        }
+        Some(p)
+    }
+
+    /// Normal at `p`, facing against `d`.
+    fn normal(&self, z: f64, p: Vec3, d: Vec3) -> Vec3 {
+        let n = (p - Vec3(0.0, 0.0, z + self.curvature_radius)).unit_vector();
+        if n.dot(&d) > 0.0 {
+            -n
+        } else {
+            n
+        }
+    }
+}
+
+#[inline]
+fn flip_z(v: Vec3) -> Vec3 {
+    Vec3(v.x(), v.y(), -v.z())
+}
+
+/// Refracts `wi`, which points away from the surface on the same side as `n`.
+fn refract(wi: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = n.dot(&wi);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(eta * -wi + (eta * cos_i - cos_t) * n)
+}
+
+/// Principal plane and focal point, in lens space, of a ray parallel to the
+/// axis entering the lens as `r_in` and leaving as `r_out`.
+fn cardinal_points(r_in: &Ray, r_out: &Ray) -> (f64, f64) {
+    let tf = -r_out.orig.x() / r_out.dir.x();
+    let tp = (r_in.orig.x() - r_out.orig.x()) / r_out.dir.x();
+    (-r_out.at(tp).z(), -r_out.at(tf).z())
+}
+
+/* ========================================== */
+/// A camera looking through a real lens, focused by moving the film. The
+/// field of view, distortion, vignetting and focus breathing all come out of
+/// the lens prescription.
+pub struct RealisticCamera {
+    frame: CameraFrame,
+    lens: LensSystem,
+    film_width: f64,
+    film_height: f64,
+    /// Radius of the disk on the rear interface that lets light through to
+    /// some part of the film.
+    pupil_radius: f64,
+    /// Fraction of that disk that lets light through to the film's center.
+    transmission: f64,
+}
+
+impl RealisticCamera {
+    /// Puts `lens` in front of a sensor `sensor_width` mm wide, focused at
+    /// `params.focus_dist`.
+    pub fn new(
+        params: &CameraParams,
+        mut lens: LensSystem,
+        sensor_width: f64,
+        aspect_ratio: f64,
+    ) -> Result<Self, String> {
+        let film_width = sensor_width / 1000.0;
+        let film_height = film_width / aspect_ratio;
+        let film_diagonal = (film_width * film_width + film_height * film_height).sqrt();
+        lens.focus(params.focus_dist, film_diagonal)
+            .ok_or_else(|| format!("The lens can't focus at {}", params.focus_dist))?;
+
+        let mut camera = RealisticCamera {
+            frame: CameraFrame::new(params.lookfrom, params.lookat, params.vup),
+            lens,
+            film_width,
+            film_height,
+            pupil_radius: 0.0,
+            transmission: 0.0,
+        };
+        camera.bound_exit_pupil(film_diagonal / 2.0);
+        if camera.transmission == 0.0 {
+            return Err("No light gets through the lens to the film".to_string());
+        }
+        Ok(camera)
+    }
+
+    /// Fraction of the light sampled at the center of the film that makes it
+    /// through the lens.
+    pub fn transmission(&self) -> f64 {
+        self.transmission
+    }
+
+    /// Whether a ray from `film` to `rear` on the rear interface gets through.
+    fn passes(&self, film: Vec3, rear: (f64, f64)) -> bool {
+        let target = Vec3(rear.0, rear.1, self.lens.rear_z());
+        self.lens
+            .trace_from_film(&Ray::new(film, target - film))
+            .is_some()
+    }
+
+    /// Finds the part of the rear interface rays from the film get through,
+    /// by tracing a grid of rays from points along the film's diagonal.
+    fn bound_exit_pupil(&mut self, half_diagonal: f64) {
+        const FILM_STEPS: usize = 16;
+        const GRID: usize = 64;
+        let rear_radius = self.lens.rear().aperture_radius;
+        let grid = |radius: f64| {
+            (0..GRID * GRID).filter_map(move |k| {
+                let x = radius * (2.0 * ((k % GRID) as f64 + 0.5) / GRID as f64 - 1.0);
+                let y = radius * (2.0 * ((k / GRID) as f64 + 0.5) / GRID as f64 - 1.0);
+                if x * x + y * y <= radius * radius {
+                    Some((x, y))
+                } else {
+                    None
+                }
+            })
+        };
+
+        let mut max_radius: f64 = 0.0;
+        for step in 0..=FILM_STEPS {
+            let film = Vec3(half_diagonal * step as f64 / FILM_STEPS as f64, 0.0, 0.0);
+            for (x, y) in grid(rear_radius) {
+                if self.passes(film, (x, y)) {
+                    max_radius = max_radius.max((x * x + y * y).sqrt());
+                }
+            }
+        }
+        // Pad by a grid cell, as the grid misses the very edge.
+        self.pupil_radius = (max_radius + 2.0 * rear_radius / GRID as f64).min(rear_radius);
+
+        let center = Vec3(0.0, 0.0, 0.0);
+        let (passed, total) = grid(self.pupil_radius).fold((0, 0), |(passed, total), p| {
+            (passed + self.passes(center, p) as u32, total + 1)
+        });
+        self.transmission = passed as f64 / total.max(1) as f64;
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn generate_ray(&self, s: f64, t: f64, lens: (f64, f64)) -> Option<Ray> {
+        // The lens turns the image upside down.
+        let film = Vec3(
+            (0.5 - s) * self.film_width,
+            (0.5 - t) * self.film_height,
+            0.0,
+        );
+        let p = self.pupil_radius * Vec3::concentric_disk(lens);
+        let target = Vec3(p.x(), p.y(), self.lens.rear_z());
+        let out = self.lens.trace_from_film(&Ray::new(film, target - film))?;
+
+        // Camera space looks down +z, the frame down -w.
+        Some(Ray::new(
+            self.frame.origin + self.frame.to_world(flip_z(out.orig)),
+            self.frame.to_world(flip_z(out.dir)),
+        ))
+    }
+
+    fn depth(&self, p: Point3) -> f64 {
+        self.frame.depth(p)
+    }
+}
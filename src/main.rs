@@ -1,49 +1,132 @@
 /*
     Identifying all the various modules in the crate
 */
+mod aabb;
+mod animation;
+mod aov;
+mod aperture;
 mod camera;
+mod checkpoint;
 mod color;
+mod csg;
+mod curve;
+mod cylinder;
+mod denoise;
+mod disk;
+mod distortion;
+mod film;
+mod filter;
+mod hair;
+mod heightfield;
 mod hittable;
+mod lens;
 mod material;
+mod options;
+mod png;
 mod ray;
+mod render;
+mod rng;
+mod roots;
+mod sampler;
+mod sdf;
 mod sphere;
+mod spectrum;
+mod stereo;
+mod texture;
+mod tonemap;
+mod torus;
 mod vec3;
 /* ======================================================= */
+use aabb::Aabb;
+use animation::Animation;
+use aperture::{Aperture, ApertureMask, ApertureShape};
 use camera::*;
+use checkpoint::Checkpoint;
 use color::Color;
+use csg::{Csg, CsgOperation};
+use curve::{Curve, CurveType};
+use cylinder::{Cone, Cylinder};
+use disk::Disk;
+use distortion::DistortedCamera;
+use film::Film;
+use hair::Hair;
+use heightfield::Heightfield;
 use hittable::*;
+use lens::{LensSystem, RealisticCamera};
 use material::*;
+use options::RenderOptions;
 use ray::*;
+use render::Renderer;
+use sampler::Sampler;
+use sdf::{Sdf, SdfObject};
 use sphere::*;
+use stereo::StereoCamera;
+use texture::{ImageTexture, SolidColor, Texture};
+use tonemap::DisplayTransform;
+use torus::Torus;
 use std::io::{stdout,Write};
+use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use vec3::*;
 use write_buf::*;
 /* ======================================================= */
 
 const INFINITY: f64 = std::f64::INFINITY;
-const ASPECT_RATIO: f64 = 4.0 / 3.0;
-const IMG_WIDTH: i32 = 800;
-const IMG_HEIGHT: i32 = (IMG_WIDTH as f64 / ASPECT_RATIO) as i32;
 
 #[inline]
-fn ray_color(r: &Ray, world: &impl Hittable, depth: i32) -> Color {
+fn ray_color(r: &Ray, world: &impl Hittable, sampler: &mut dyn Sampler, depth: i32) -> Color {
     if depth < 0 {
         return Vec3(0.0, 0.0, 0.0);
     }
 
     if let Some(rec) = world.hit(r, 0.001, INFINITY) {
         let mut attenuation = Color::default();
+        let mat = rec.mat_ptr.as_ref().unwrap();
+        let emitted = mat.emitted(&rec);
 
-        if let Some(ref scattered) =
-            rec.mat_ptr
-                .as_ref()
-                .unwrap()
-                .scatter(r, &rec, &mut attenuation)
-        {
-            return attenuation * ray_color(scattered, world, depth - 1);
+        let sample = sampler.get_2d();
+        if let Some(mut scattered) = mat.scatter_sampled(r, &rec, &mut attenuation, sample) {
+            scattered.time = r.time;
+            return emitted + attenuation * ray_color(&scattered, world, sampler, depth - 1);
         }
-        return Color::new(0.0, 0.0, 0.0);
+        return emitted;
     }
+    sky_color(r)
+}
+
+/// Traces a single wavelength path, `r.wavelength` must be set.
+fn ray_color_spectral(
+    r: &Ray,
+    world: &impl Hittable,
+    sampler: &mut dyn Sampler,
+    depth: i32,
+) -> f64 {
+    if depth < 0 {
+        return 0.0;
+    }
+    let lambda = r.wavelength.expect("Spectral ray without a wavelength");
+
+    if let Some(rec) = world.hit(r, 0.001, INFINITY) {
+        let mut attenuation = Color::default();
+        let mat = rec.mat_ptr.as_ref().unwrap();
+        let emitted = spectrum::rgb_to_spectrum(mat.emitted(&rec), lambda);
+
+        let sample = sampler.get_2d();
+        if let Some(mut scattered) = mat.scatter_sampled(r, &rec, &mut attenuation, sample) {
+            scattered.wavelength = r.wavelength;
+            scattered.time = r.time;
+            return emitted
+                + spectrum::rgb_to_spectrum(attenuation, lambda)
+                    * ray_color_spectral(&scattered, world, sampler, depth - 1);
+        }
+        return emitted;
+    }
+    spectrum::rgb_to_spectrum(sky_color(r), lambda)
+}
+
+#[inline]
+fn sky_color(r: &Ray) -> Color {
     let unit_dir = r.dir().unit_vector();
     let t: f64 = 0.5 * (unit_dir.y() + 1.0);
     (1.0 - t) * Color::default() + t * Color::new(0.5, 0.7, 1.0)
@@ -61,36 +144,296 @@ fn clamp(x: f64, min: f64, max: f64) -> f64 {
 }
 
 fn main() {
-    let mut writer = WriteBufVec::new(stdout());
-    // * IMAGE
-    const SAMPLES_PER_PIXEL: i32 = 100;
-    const MAX_DEPTH: i32 = 50;
+    let opts = RenderOptions::from_args();
+    let checkpoint = opts.resume.as_ref().map(|path| {
+        Checkpoint::load(path).unwrap_or_else(|e| {
+            eprintln!("Couldnt read checkpoint {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+    let animation = opts.animation.as_ref().map(|path| {
+        Animation::load(path).unwrap_or_else(|e| {
+            eprintln!("Couldnt read animation {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
 
     // * WORLD and CAMERA
-    let (world, cam) = random_scene();
+    let seed = opts
+        .seed
+        .or_else(|| checkpoint.as_ref().map(|c| c.seed))
+        .unwrap_or_else(rand::random);
+    eprintln!("Scene seed: {}", seed);
+    if let Some(frames) = opts.frames {
+        render_frames(&opts, seed, animation.unwrap_or_default(), frames);
+        eprintln! {"Done"};
+        return;
+    }
+    let (world, cam, exposure) = build_scene(&opts, seed, animation.as_ref().map(|a| (a, 0.0)));
+    let display = DisplayTransform {
+        exposure: opts.display.exposure + exposure,
+        ..opts.display
+    };
+    let settings_hash = opts.settings_hash(seed);
 
     // * RENDER
-    writeln!(writer, "P3\n{} {}\n255", IMG_WIDTH, IMG_HEIGHT).unwrap();
-    for j in (0..IMG_HEIGHT).rev() {
-        eprintln!("Lines Remaining: {}", j);
-        for i in 0..IMG_WIDTH {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-            for _ in 0..SAMPLES_PER_PIXEL {
-                let u = (i as f64 + random_f64()) / (IMG_WIDTH - 1) as f64;
-                let v = (j as f64 + random_f64()) / (IMG_HEIGHT - 1) as f64;
-                let r = cam.get_ray(u, v);
-                pixel_color += ray_color(&r, &world, MAX_DEPTH);
+    let renderer = Renderer::new(&world, &*cam, &opts);
+    let film = if opts.is_progressive() {
+        let film = match checkpoint {
+            Some(checkpoint) => {
+                let film = &checkpoint.film;
+                if film.width != opts.width || film.height != opts.height {
+                    eprintln!(
+                        "The checkpoint is {}x{}, not {}x{}",
+                        film.width, film.height, opts.width, opts.height
+                    );
+                    std::process::exit(1);
+                }
+                if checkpoint.settings_hash != settings_hash {
+                    eprintln!("The checkpoint was taken with a different scene or settings");
+                    std::process::exit(1);
+                }
+                if opts.wants_aovs() && checkpoint.film.aovs.is_none() {
+                    eprintln!("The checkpoint has no AOVs to save or denoise with");
+                    std::process::exit(1);
+                }
+                rng::set_state(checkpoint.rng_state);
+                checkpoint.film
+            }
+            None => Film::new(opts.width, opts.height),
+        };
+        let checkpoint_path = opts.checkpoint.as_ref().or(opts.resume.as_ref());
+        let save_checkpoint = |film: &Film| {
+            if let Some(path) = checkpoint_path {
+                let checkpoint = Checkpoint {
+                    settings_hash,
+                    seed,
+                    rng_state: rng::state(),
+                    film: film.clone(),
+                };
+                checkpoint.save(path).expect("Couldnt save the checkpoint");
+            }
+        };
+
+        // Ctrl-C stops the render after the current row, the image so far is still saved.
+        let stop = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))
+            .expect("Couldnt install the Ctrl-C handler");
+        let output = opts.output.clone().unwrap_or_else(|| "render.ppm".to_string());
+        let film = renderer.render_progressive(film, &stop, |film| {
+            finish(film, &opts).save(&output, &display).expect("Couldnt save the snapshot");
+            save_checkpoint(film);
+        });
+        finish(&film, &opts).save(&output, &display).expect("Couldnt save the image");
+        save_checkpoint(&film);
+        film
+    } else {
+        let film = renderer.render(opts.width, opts.height);
+        match opts.output {
+            Some(ref path) => finish(&film, &opts)
+                .save(path, &display)
+                .expect("Couldnt save the image"),
+            None => {
+                let mut writer = WriteBufVec::new(stdout());
+                finish(&film, &opts).write_ppm(&mut writer, &display).unwrap();
+                writer.flush().expect("Couldnt Flush Writer");
             }
-            color::write_color(&mut writer, pixel_color, SAMPLES_PER_PIXEL).unwrap();
         }
-    }
+        film
+    };
 
-    writer.flush().expect("Couldnt Flush Writer");
+    save_buffers(&film, &opts, None);
     eprintln! {"Done"};
 }
 
+/// Builds the scene and the camera looking at it, posed as at a frame of an
+/// animation if given one. Also returns the exposure change, in stops, the
+/// camera calls for.
+fn build_scene(
+    opts: &RenderOptions,
+    seed: u64,
+    animation: Option<(&Animation, f64)>,
+) -> (HittableList, Box<dyn Camera>, f64) {
+    rng::seed(seed);
+    let (mut world, mut params) = match opts.scene.as_str() {
+        "random" => random_scene(false),
+        "motion" => random_scene(true),
+        "simple" => init_world(),
+        "fov" => init_world2(),
+        "shapes" => shapes_scene(),
+        "materials" => materials_scene(),
+        "dispersion" => dispersion_scene(),
+        "maps" => maps_scene(),
+        "csg" => csg_scene(),
+        "sdf" => sdf_scene(),
+        "terrain" => terrain_scene(),
+        "hair" => hair_scene(),
+        other => {
+            eprintln!("Unknown scene: {}", other);
+            std::process::exit(2);
+        }
+    };
+    if let Some(ref path) = opts.curves {
+        let hair = Hair::from_melanin(1.3, 0.0, 0.3, 0.3).into();
+        let groom = Curve::load(path, opts.curve_type, &hair).unwrap_or_else(|e| {
+            eprintln!("Couldnt read curves {}: {}", path, e);
+            std::process::exit(1);
+        });
+        let groom: Rc<dyn Hittable> = Rc::new(groom);
+        world.add(&groom);
+    }
+    let aspect_ratio = match opts.stereo {
+        Some(ref rig) => rig.layout.eye_aspect_ratio(opts.width, opts.height),
+        None => opts.width as f64 / opts.height as f64,
+    };
+    let mut exposure = 0.0;
+    if let Some(ref physical) = opts.physical {
+        physical.apply(&mut params, aspect_ratio);
+        exposure += physical.exposure_scale().log2();
+        eprintln!(
+            "Exposure: EV100 {:.1}, {:.1}° vertical field of view",
+            physical.ev100(),
+            params.vfov
+        );
+    }
+    if let Some((animation, frame)) = animation {
+        animation.apply(frame, &mut world, &mut params);
+    }
+    if let Some(fov) = opts.fov {
+        params.vfov = fov;
+    } else if let (Projection::Fisheye(_), None) = (opts.projection, opts.physical) {
+        params.vfov = 180.0;
+    }
+    let shape = match (&opts.aperture_mask, opts.blades) {
+        (Some(path), _) => {
+            let mask = texture::ImageTexture::load_ppm(path)
+                .ok()
+                .and_then(|image| ApertureMask::new(&image))
+                .unwrap_or_else(|| {
+                    eprintln!("Couldnt read aperture mask {}", path);
+                    std::process::exit(1);
+                });
+            ApertureShape::Mask(Rc::new(mask))
+        }
+        (None, Some(blades)) => ApertureShape::Polygon {
+            blades,
+            rotation: deg_to_rad(opts.blade_rotation),
+        },
+        (None, None) => ApertureShape::Circle,
+    };
+    params.aperture_shape = Aperture::new(shape, opts.cat_eye);
+    let mut cam: Box<dyn Camera> = match (&opts.lens, opts.stereo) {
+        (Some(_), Some(_)) => {
+            eprintln!("Stereo rigs can't look through a lens prescription");
+            std::process::exit(2);
+        }
+        (Some(path), None) => {
+            let lens = LensSystem::load(path).unwrap_or_else(|e| {
+                eprintln!("Couldnt read lens {}: {}", path, e);
+                std::process::exit(1);
+            });
+            let sensor_width = opts.physical.map_or(36.0, |p| p.sensor_width);
+            let cam = RealisticCamera::new(&params, lens, sensor_width, aspect_ratio)
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
+            // Expose for the light reaching the center of the film.
+            exposure -= cam.transmission().log2();
+            Box::new(cam)
+        }
+        (None, Some(ref rig)) => {
+            Box::new(StereoCamera::new(&params, opts.projection, aspect_ratio, rig))
+        }
+        (None, None) => params.build(opts.projection, aspect_ratio),
+    };
+    if let Some(effects) = opts.lens_effects {
+        if opts.stereo.is_some() {
+            eprintln!("Lens effects can't be applied to stereo rigs");
+            std::process::exit(2);
+        }
+        // The distortion model works in the normalized coordinates of a
+        // pinhole, which only the plain perspective camera has.
+        if opts.projection != Projection::Perspective || opts.lens.is_some() {
+            eprintln!("Lens effects need the perspective projection, without a lens prescription");
+            std::process::exit(2);
+        }
+        let half_height = (deg_to_rad(params.vfov) / 2.0).tan();
+        cam = Box::new(DistortedCamera::new(cam, effects, half_height, aspect_ratio));
+    }
+    (world, cam, exposure)
+}
+
+/// Renders frames `first` to `last` of an animation, each to the output path
+/// with the frame number filled in, see `animation::frame_path`.
+fn render_frames(
+    opts: &RenderOptions,
+    seed: u64,
+    animation: Animation,
+    (first, last): (u32, u32),
+) {
+    if opts.is_progressive() {
+        eprintln!("Frame ranges can't be rendered progressively");
+        std::process::exit(2);
+    }
+    let pattern = opts.output.clone().unwrap_or_else(|| "frame_%04d.ppm".to_string());
+    for frame in first..=last {
+        eprintln!("Frame {}", frame);
+        let (world, cam, exposure) = build_scene(opts, seed, Some((&animation, frame as f64)));
+        let display = DisplayTransform {
+            exposure: opts.display.exposure + exposure,
+            ..opts.display
+        };
+        let film = Renderer::new(&world, &*cam, opts).render(opts.width, opts.height);
+        let path = animation::frame_path(&pattern, frame);
+        finish(&film, opts).save(&path, &display).expect("Couldnt save the frame");
+        save_buffers(&film, opts, Some(frame));
+    }
+}
+
+/// The film as it is saved, cropped and denoised if asked. Checkpoints keep
+/// the raw samples.
+fn finish(film: &Film, opts: &RenderOptions) -> Film {
+    let mut image = output_region(film, opts);
+    if let Some(ref settings) = opts.denoise {
+        denoise::denoise(&mut image, settings);
+    }
+    image
+}
+
+/// The part of the film that is saved: only the crop window, unless the
+/// whole frame is asked for.
+fn output_region(film: &Film, opts: &RenderOptions) -> Film {
+    match opts.crop {
+        Some(ref crop) if !opts.crop_full => film.crop(crop),
+        _ => film.clone(),
+    }
+}
+
+/// Saves the AOVs and the samples-per-pixel map, if asked, numbered when
+/// rendering a frame range.
+fn save_buffers(film: &Film, opts: &RenderOptions, frame: Option<u32>) {
+    let film = &output_region(film, opts);
+    if let (Some(prefix), Some(aovs)) = (&opts.aov_prefix, &film.aovs) {
+        let prefix = match frame {
+            Some(frame) => format!("{}_{:04}", prefix, frame),
+            None => prefix.clone(),
+        };
+        aovs.save(&prefix).expect("Couldnt save the AOVs");
+    }
+    if let Some(ref path) = opts.sample_map {
+        let path = match frame {
+            Some(frame) => animation::frame_path(path, frame),
+            None => path.clone(),
+        };
+        let mut file = std::fs::File::create(path).expect("Couldnt create the sample map");
+        film.write_sample_count_ppm(&mut file, opts.samples_per_pixel)
+            .expect("Couldnt write the sample map");
+    }
+}
+
 // * Init the objects in the world.
-fn init_world() -> (HittableList, Camera) {
+fn init_world() -> (HittableList, CameraParams) {
     let mut world = HittableList::new();
 
     // =================================================================
@@ -120,20 +463,20 @@ fn init_world() -> (HittableList, Camera) {
     let vup = Vec3(0.0, 1.0, 0.0);
     let dist_to_focus = (lookfrom - lookat).length();
     let aperture = 0.01;
-    let cam = Camera::new(
+    let cam = CameraParams {
         lookfrom,
         lookat,
         vup,
-        20.0,
-        ASPECT_RATIO,
+        vfov: 20.0,
         aperture,
-        dist_to_focus,
-    );
+        aperture_shape: Aperture::default(),
+        focus_dist: dist_to_focus,
+    };
 
     (world, cam)
 }
 
-fn init_world2() -> (HittableList, Camera) {
+fn init_world2() -> (HittableList, CameraParams) {
     // ==========================================================================
     // Testing FOV in the camera
     let r = (PI / 4.0).cos();
@@ -149,20 +492,401 @@ fn init_world2() -> (HittableList, Camera) {
     let vup = Vec3(0.0, 1.0, 0.0);
     let dist_to_focus = (lookfrom - lookat).length();
     let aperture = 0.1;
-    let cam = Camera::new(
+    let cam = CameraParams {
         lookfrom,
         lookat,
         vup,
-        20.0,
-        ASPECT_RATIO,
+        vfov: 20.0,
         aperture,
-        dist_to_focus,
-    );
+        aperture_shape: Aperture::default(),
+        focus_dist: dist_to_focus,
+    };
+
+    (world, cam)
+}
+
+/// A row of spheres showing off the principled material: plastic, brushed
+/// gold, clearcoated paint, velvet and frosted glass.
+fn materials_scene() -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+    world.add(&Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, &ground).into());
+
+    let materials = vec![
+        Principled {
+            base_color: Vec3(0.7, 0.1, 0.1),
+            roughness: 0.2,
+            ..Default::default()
+        },
+        Principled {
+            base_color: Vec3(1.0, 0.78, 0.34),
+            metallic: 1.0,
+            roughness: 0.4,
+            ..Default::default()
+        },
+        Principled {
+            base_color: Vec3(0.1, 0.2, 0.6),
+            clearcoat: 1.0,
+            ..Default::default()
+        },
+        Principled {
+            base_color: Vec3(0.4, 0.1, 0.4),
+            roughness: 1.0,
+            specular: 0.0,
+            sheen: 1.0,
+            ..Default::default()
+        },
+        Principled {
+            base_color: Vec3(0.9, 1.0, 0.95),
+            roughness: 0.1,
+            transmission: 1.0,
+            ior: 1.5,
+            ..Default::default()
+        },
+    ];
+    for (i, material) in materials.into_iter().enumerate() {
+        let material = material.into();
+        let center = Vec3(-4.4 + 2.2 * i as f64, 1.0, 0.0);
+        world.add(&Sphere::new(center, 1.0, &material).into());
+    }
+
+    let lookfrom = Vec3(0.0, 3.0, 12.0);
+    let lookat = Vec3(0.0, 1.0, 0.0);
+    let cam = CameraParams {
+        lookfrom,
+        lookat,
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        aperture: 0.0,
+        aperture_shape: Aperture::default(),
+        focus_dist: (lookfrom - lookat).length(),
+    };
+
+    (world, cam)
+}
+
+/// Glass spheres from plain to strongly dispersive, for rendering with
+/// `--spectral`: constant IOR, crown and dense flint glass.
+fn dispersion_scene() -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+    world.add(&Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, &ground).into());
+
+    let plain = Dielectric::new(1.5).into();
+    let crown = Dielectric::dispersive(Ior::BK7).into();
+    let flint = Dielectric::dispersive(Ior::SF11).into();
+    world.add(&Sphere::new(Vec3(-2.2, 1.0, 0.0), 1.0, &plain).into());
+    world.add(&Sphere::new(Vec3(0.0, 1.0, 0.0), 1.0, &crown).into());
+    world.add(&Sphere::new(Vec3(2.2, 1.0, 0.0), 1.0, &flint).into());
+
+    let lookfrom = Vec3(0.0, 2.0, 9.0);
+    let lookat = Vec3(0.0, 1.0, 0.0);
+    let cam = CameraParams {
+        lookfrom,
+        lookat,
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        aperture: 0.0,
+        aperture_shape: Aperture::default(),
+        focus_dist: (lookfrom - lookat).length(),
+    };
+
+    (world, cam)
+}
+
+/// An image texture filled in by `f`, from (u, v) to color.
+fn procedural_texture(width: usize, height: usize, f: impl Fn(f64, f64) -> Color) -> ImageTexture {
+    let mut data = Vec::with_capacity(width * height);
+    // Rows run from the top of the image, where v = 1.
+    for j in 0..height {
+        for i in 0..width {
+            let u = (i as f64 + 0.5) / width as f64;
+            let v = 1.0 - (j as f64 + 0.5) / height as f64;
+            data.push(f(u, v));
+        }
+    }
+    ImageTexture::new(width, height, data)
+}
+
+/// The same clay sphere plain, bumped by a height map and ridged by a
+/// normal map, in front of a lattice cut out of a sphere by an alpha mask and
+/// a sphere faded out stochastically.
+fn maps_scene() -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+    world.add(&Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, &ground).into());
+
+    let clay: Rc<dyn Material> = Lambertian::new(Vec3(0.7, 0.45, 0.3)).into();
+    let dimples: Rc<dyn Texture> = procedural_texture(512, 256, |u, v| {
+        let h = 0.5 + 0.5 * (2.0 * PI * 16.0 * u).sin() * (2.0 * PI * 8.0 * v).sin();
+        Vec3(h, h, h)
+    })
+    .into();
+    let ridges: Rc<dyn Texture> = procedural_texture(512, 256, |u, _| {
+        // Tangent space normals tilting back and forth along u.
+        let tilt = 0.6 * (2.0 * PI * 16.0 * u).sin();
+        let n = Vec3(tilt, 0.0, 1.0).unit_vector();
+        0.5 * (n + Vec3(1.0, 1.0, 1.0))
+    })
+    .into();
+    let bumped = BumpMapped::new(&clay, &dimples, 0.03).into();
+    let ridged = NormalMapped::new(&clay, &ridges).into();
+    world.add(&Sphere::new(Vec3(-2.2, 1.0, 0.0), 1.0, &clay).into());
+    world.add(&Sphere::new(Vec3(0.0, 1.0, 0.0), 1.0, &bumped).into());
+    world.add(&Sphere::new(Vec3(2.2, 1.0, 0.0), 1.0, &ridged).into());
+
+    let lattice: Rc<dyn Texture> = procedural_texture(512, 256, |u, v| {
+        let bar = |x: f64| (x - x.round()).abs() < 0.12;
+        if bar(24.0 * u) || bar(12.0 * v) {
+            Vec3(1.0, 1.0, 1.0)
+        } else {
+            Vec3(0.0, 0.0, 0.0)
+        }
+    })
+    .into();
+    let faded: Rc<dyn Texture> = SolidColor::new(Vec3(0.4, 0.4, 0.4)).into();
+    let blue = Lambertian::new(Vec3(0.2, 0.3, 0.7)).into();
+    let cage: Rc<dyn Hittable> = Sphere::new(Vec3(-1.2, 1.4, -3.0), 1.4, &blue).into();
+    let ghost: Rc<dyn Hittable> = Sphere::new(Vec3(2.0, 1.4, -3.0), 1.4, &blue).into();
+    world.add(&AlphaMasked::new(&cage, &lattice, AlphaMode::Threshold(0.5)).into());
+    world.add(&AlphaMasked::new(&ghost, &faded, AlphaMode::Stochastic).into());
+
+    let lookfrom = Vec3(0.0, 2.0, 9.0);
+    let lookat = Vec3(0.0, 1.0, 0.0);
+    let cam = CameraParams {
+        lookfrom,
+        lookat,
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        aperture: 0.0,
+        aperture_shape: Aperture::default(),
+        focus_dist: (lookfrom - lookat).length(),
+    };
+
+    (world, cam)
+}
+
+/// The analytic shapes other than spheres, side by side.
+fn shapes_scene() -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+    let red = Lambertian::new(Vec3(0.7, 0.2, 0.2)).into();
+    let green = Lambertian::new(Vec3(0.2, 0.6, 0.3)).into();
+    let blue = Lambertian::new(Vec3(0.2, 0.3, 0.7)).into();
+    let gold = Metal::new(Vec3(0.8, 0.6, 0.2), 0.1).into();
+    let glass = Dielectric::new(1.5).into();
+
+    world.add(&Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, &ground).into());
+    world.add(&Cylinder::new(Vec3(-3.0, 0.0, 0.0), 0.7, 1.6, true, &red).into());
+    world.add(&Cylinder::new(Vec3(-3.0, 0.0, 2.2), 0.5, 1.0, false, &blue).into());
+    world.add(&Cone::new(Vec3(-1.0, 0.0, 0.0), 0.7, 1.8, true, &green).into());
+    world.add(&Torus::new(Vec3(1.2, 0.35, 0.0), 0.8, 0.35, &gold).into());
+    world.add(&Torus::new(Vec3(1.2, 0.25, 2.2), 0.5, 0.25, &glass).into());
+    world.add(&Disk::annulus(Vec3(3.2, 0.01, 0.0), 0.3, 0.8, &blue).into());
+    world.add(&Disk::new(Vec3(-1.0, 0.01, 2.2), 0.6, &red).into());
+
+    let lookfrom = Vec3(0.0, 5.0, 9.0);
+    let lookat = Vec3(0.0, 0.5, 0.5);
+    let cam = CameraParams {
+        lookfrom,
+        lookat,
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 40.0,
+        aperture: 0.0,
+        aperture_shape: Aperture::default(),
+        focus_dist: (lookfrom - lookat).length(),
+    };
+
+    (world, cam)
+}
+
+/// Solids built from spheres and cylinders with boolean operations.
+fn csg_scene() -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+    let red = Lambertian::new(Vec3(0.7, 0.2, 0.2)).into();
+    let gold = Metal::new(Vec3(0.8, 0.6, 0.2), 0.2).into();
+    let glass = Dielectric::new(1.5).into();
+    world.add(&Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, &ground).into());
+
+    // A sphere with a cylindrical hole drilled down through it.
+    let ball = Sphere::new(Vec3(-2.2, 1.0, 0.0), 1.0, &red).into();
+    let drill = Cylinder::new(Vec3(-2.2, -0.5, 0.0), 0.45, 3.0, true, &red).into();
+    world.add(&Csg::new(CsgOperation::Difference, &ball, &drill).into());
+
+    // A lens: the intersection of two overlapping spheres.
+    let left = Sphere::new(Vec3(-1.2, 1.0, 0.0), 1.5, &glass).into();
+    let right = Sphere::new(Vec3(1.2, 1.0, 0.0), 1.5, &glass).into();
+    world.add(&Csg::new(CsgOperation::Intersection, &left, &right).into());
+
+    // Three cylinders joined into a jack, its ends rounded off by a sphere.
+    let bar: Rc<dyn Hittable> = Cylinder::new(Vec3(0.0, -0.9, 0.0), 0.3, 1.8, true, &gold).into();
+    let turned = |rotation: Vec3| -> Rc<dyn Hittable> {
+        Transformed::new(&bar, Vec3(0.0, 0.0, 0.0), rotation, 1.0, None).into()
+    };
+    let cross = Csg::new(CsgOperation::Union, &bar, &turned(Vec3(0.0, 0.0, 90.0))).into();
+    let jack = Csg::new(CsgOperation::Union, &cross, &turned(Vec3(90.0, 0.0, 0.0))).into();
+    let rounding = Sphere::new(Vec3(0.0, 0.0, 0.0), 0.8, &gold).into();
+    let jack = Csg::new(CsgOperation::Intersection, &jack, &rounding).into();
+    let tilt = Vec3(30.0, 20.0, 0.0);
+    world.add(&Transformed::new(&jack, Vec3(2.4, 1.0, 0.0), tilt, 1.0, None).into());
+
+    let lookfrom = Vec3(0.0, 3.5, 9.0);
+    let lookat = Vec3(0.0, 0.8, 0.0);
+    let cam = CameraParams {
+        lookfrom,
+        lookat,
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        aperture: 0.0,
+        aperture_shape: Aperture::default(),
+        focus_dist: (lookfrom - lookat).length(),
+    };
+
+    (world, cam)
+}
+
+/// Shapes without a mesh, drawn from signed distance functions.
+fn sdf_scene() -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+    let teal = Lambertian::new(Vec3(0.2, 0.55, 0.55)).into();
+    let copper = Metal::new(Vec3(0.85, 0.5, 0.35), 0.15).into();
+    let pink = Lambertian::new(Vec3(0.8, 0.35, 0.5)).into();
+    world.add(&Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, &ground).into());
+
+    // A ball melting into a ring.
+    let blob = Sdf::Sphere { radius: 0.6 }
+        .translated(Vec3(0.0, 0.6, 0.0))
+        .union(
+            Sdf::Torus {
+                major_radius: 0.9,
+                minor_radius: 0.2,
+            }
+            .translated(Vec3(0.0, 0.2, 0.0)),
+            0.3,
+        );
+    let bounds = Aabb::new(Vec3(-3.6, 0.0, -1.2), Vec3(-1.2, 1.3, 1.2));
+    let blob = blob.translated(Vec3(-2.4, 0.0, 0.0));
+    world.add(&SdfObject::new(blob, bounds, &teal).into());
+
+    // A twisted column with a rounded notch carved out of it.
+    let column = Sdf::Box {
+        half_size: Vec3(0.4, 1.0, 0.4),
+    }
+    .twisted(1.2)
+    .subtract(
+        Sdf::Sphere { radius: 0.45 }.translated(Vec3(0.45, 0.3, 0.45)),
+        0.1,
+    )
+    .translated(Vec3(0.0, 1.0, 0.0));
+    let bounds = Aabb::new(Vec3(-0.6, 0.0, -0.6), Vec3(0.6, 2.0, 0.6));
+    world.add(&SdfObject::new(column, bounds, &copper).into());
+
+    // A lattice of capsules, kept to a block by its bounds.
+    let lattice = Sdf::Capsule {
+        a: Vec3(-0.12, 0.0, 0.0),
+        b: Vec3(0.12, 0.0, 0.0),
+        radius: 0.08,
+    }
+    .repeated(Vec3(0.4, 0.4, 0.4))
+    .translated(Vec3(2.4, 0.2, 0.0));
+    let bounds = Aabb::new(Vec3(1.6, 0.0, -0.8), Vec3(3.2, 1.3, 0.8));
+    world.add(&SdfObject::new(lattice, bounds, &pink).into());
+
+    let lookfrom = Vec3(0.0, 3.0, 8.0);
+    let lookat = Vec3(0.0, 0.8, 0.0);
+    let cam = CameraParams {
+        lookfrom,
+        lookat,
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        aperture: 0.0,
+        aperture_shape: Aperture::default(),
+        focus_dist: (lookfrom - lookat).length(),
+    };
+
+    (world, cam)
+}
+
+/// Hills of noise, with lakes in the valleys.
+fn terrain_scene() -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
+    let grass = Lambertian::new(Vec3(0.35, 0.5, 0.25)).into();
+    let water = Metal::new(Vec3(0.5, 0.6, 0.7), 0.05).into();
+
+    let size = Vec3(40.0, 10.0, 40.0);
+    let corner = Vec3(-20.0, 0.0, -30.0);
+    world.add(&Heightfield::from_noise(513, 7, corner, size, &grass).into());
+    world.add(&Sphere::new(Vec3(0.0, -1000.0 + 3.0, 0.0), 1000.0, &water).into());
+
+    let lookfrom = Vec3(0.0, 10.0, 8.0);
+    let lookat = Vec3(0.0, 2.0, -8.0);
+    let cam = CameraParams {
+        lookfrom,
+        lookat,
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 50.0,
+        aperture: 0.0,
+        aperture_shape: Aperture::default(),
+        focus_dist: (lookfrom - lookat).length(),
+    };
+
+    (world, cam)
+}
+
+/// A head of wavy hair, grown from a sphere as Bezier curves.
+fn hair_scene() -> (HittableList, CameraParams) {
+    let mut world = HittableList::new();
+    let ground = Lambertian::new(Vec3(0.5, 0.5, 0.5)).into();
+    let skin = Lambertian::new(Vec3(0.8, 0.6, 0.5)).into();
+    let hair: Rc<dyn Material> = Hair::from_melanin(1.3, 0.2, 0.25, 0.3).into();
+
+    world.add(&Sphere::new(Vec3(0.0, -1000.0, 0.0), 1000.0, &ground).into());
+    let center = Vec3(0.0, 1.0, 0.0);
+    let radius = 0.5;
+    world.add(&Sphere::new(center, radius, &skin).into());
+
+    // Roots spread evenly over the top half of the head, on a Fibonacci
+    // spiral, with strands falling outwards and down.
+    let strands = 600;
+    let golden_angle = PI * (3.0 - 5f64.sqrt());
+    for i in 0..strands {
+        let y = 1.0 - i as f64 / strands as f64 * 0.9;
+        let ring = (1.0 - y * y).sqrt();
+        let angle = golden_angle * i as f64;
+        let normal = Vec3(ring * angle.cos(), y, ring * angle.sin());
+        let outwards = Vec3(normal.0, 0.0, normal.2);
+        let length = random_range(0.8, 1.1);
+        let wave = 0.05 * Vec3::random_in_unit_sphere();
+
+        let root = center + radius * normal;
+        let cp1 = root + 0.3 * length * normal + wave;
+        let cp2 = cp1 + 0.3 * length * outwards + Vec3(0.0, -0.4 * length, 0.0) - wave;
+        let tip = cp2 + Vec3(0.0, -0.4 * length, 0.0) + 0.1 * outwards + wave;
+        let width = (0.012, 0.004);
+        let curve = Curve::new([root, cp1, cp2, tip], width, CurveType::Ribbon(None), &hair);
+        world.add(&curve.into());
+    }
+
+    let lookfrom = Vec3(1.5, 1.6, 3.0);
+    let lookat = Vec3(0.0, 0.9, 0.0);
+    let cam = CameraParams {
+        lookfrom,
+        lookat,
+        vup: Vec3(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        aperture: 0.0,
+        aperture_shape: Aperture::default(),
+        focus_dist: (lookfrom - lookat).length(),
+    };
 
     (world, cam)
 }
 
-fn random_scene() -> (HittableList, Camera) {
+/// The cover scene. With `bouncing` the diffuse spheres move upwards, blurred
+/// over the shutter interval.
+fn random_scene(bouncing: bool) -> (HittableList, CameraParams) {
     // * RANDOM SCENE:
     let mut world = HittableList::new();
 
@@ -182,7 +906,13 @@ fn random_scene() -> (HittableList, Camera) {
             if (center - Vec3(4.0, 0.2, 0.0)).length() > 0.9 {
                 let sph_mat = if choose_mat < 0.8 {
                     // Diffuse
-                    Lambertian::new(Color::random() * Color::random()).into()
+                    let sph_mat = Lambertian::new(Color::random() * Color::random()).into();
+                    if bouncing {
+                        let velocity = Vec3(0.0, random_range(0.0, 15.0), 0.0);
+                        world.add(&Sphere::moving(center, velocity, 0.2, &sph_mat).into());
+                        continue;
+                    }
+                    sph_mat
                 } else if choose_mat < 0.95 {
                     // metal
                     Metal::new(Color::random_range(0.5, 1.0), random_range(0.5, 1.0)).into()
@@ -210,15 +940,15 @@ fn random_scene() -> (HittableList, Camera) {
     let vup = Vec3(0.0, 1.0, 0.0);
     let dist_to_focus = 10.0;
     let aperture = 0.1;
-    let cam = Camera::new(
+    let cam = CameraParams {
         lookfrom,
         lookat,
         vup,
-        20.0,
-        ASPECT_RATIO,
+        vfov: 20.0,
         aperture,
-        dist_to_focus,
-    );
+        aperture_shape: Aperture::default(),
+        focus_dist: dist_to_focus,
+    };
 
     (world, cam)
 }
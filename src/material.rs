@@ -1,4 +1,5 @@
 use crate::ray::Ray;
+use crate::texture::Texture;
 use crate::vec3::Vec3;
 use crate::{clamp, color};
 use crate::{hittable::HitRecord, vec3::random_f64};
@@ -6,6 +7,31 @@ use std::rc::Rc;
 pub trait Material {
     fn scatter(&self, ray_in: &Ray, rec: &HitRecord, attenuation: &mut color::Color)
         -> Option<Ray>;
+
+    /// Like `scatter`, but draws the scattered direction from a sample of the
+    /// unit square. Materials that don't support it ignore the sample: only
+    /// `Lambertian` and `Dielectric` (and the wrappers around them) use it,
+    /// `Metal` fuzz, `Principled` and `Hair` still draw from `random_f64` and
+    /// get no benefit from low-discrepancy samplers.
+    fn scatter_sampled(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+        _sample: (f64, f64),
+    ) -> Option<Ray> {
+        self.scatter(ray_in, rec, attenuation)
+    }
+
+    /// Light emitted by the surface, black for everything that isn't a light.
+    fn emitted(&self, _rec: &HitRecord) -> color::Color {
+        color::Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// The surface's overall reflectance, written to the albedo AOV.
+    fn albedo(&self, _rec: &HitRecord) -> color::Color {
+        color::Color::new(1.0, 1.0, 1.0)
+    }
 }
 /* ========================================== */
 #[derive(Default)]
@@ -19,14 +45,35 @@ impl Lambertian {
     }
 }
 
-impl Material for Lambertian {
-    fn scatter(&self, _: &Ray, rec: &HitRecord, attenuation: &mut color::Color) -> Option<Ray> {
-        let mut scatter_dir = rec.normal + Vec3::random_unit_vector();
+impl Lambertian {
+    fn scatter_towards(&self, rec: &HitRecord, unit: Vec3, attenuation: &mut color::Color) -> Ray {
+        let mut scatter_dir = rec.normal + unit;
         if scatter_dir.near_zero() {
             scatter_dir = rec.normal;
         }
         *attenuation = self.albedo;
-        Some(Ray::new(rec.p, scatter_dir))
+        Ray::new(rec.p, scatter_dir)
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, _: &Ray, rec: &HitRecord, attenuation: &mut color::Color) -> Option<Ray> {
+        Some(self.scatter_towards(rec, Vec3::random_unit_vector(), attenuation))
+    }
+
+    fn scatter_sampled(
+        &self,
+        _: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+        sample: (f64, f64),
+    ) -> Option<Ray> {
+        let unit = Vec3::unit_vector_from_sample(sample);
+        Some(self.scatter_towards(rec, unit, attenuation))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> color::Color {
+        self.albedo
     }
 }
 /* ============================================= */
@@ -63,14 +110,72 @@ impl Material for Metal {
             None
         }
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> color::Color {
+        self.albedo
+    }
+}
+
+/// Index of refraction, either constant or varying with wavelength.
+#[derive(Clone, Copy)]
+pub enum Ior {
+    Constant(f64),
+    /// n = a + b / λ², with λ in micrometres.
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// n² = 1 + Σ bᵢλ² / (λ² - cᵢ), with λ in micrometres.
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl Ior {
+    /// Schott N-BK7 crown glass.
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    /// Schott N-SF11 dense flint glass, about four times as dispersive as BK7.
+    pub const SF11: Ior = Ior::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+
+    /// Sodium d-line, the wavelength glass catalogues quote `n` at. Used when
+    /// rendering in RGB.
+    pub const REFERENCE_WAVELENGTH: f64 = 587.6;
+
+    pub fn at(&self, lambda_nm: f64) -> f64 {
+        let l = lambda_nm * 1e-3;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / (l * l),
+            Ior::Sellmeier { b, c } => {
+                let l2 = l * l;
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
 }
 
 pub struct Dielectric {
-    ir: f64,
+    ir: Ior,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
+        Dielectric {
+            ir: Ior::Constant(ir),
+        }
+    }
+
+    /// A dispersive dielectric, whose IOR depends on the ray's wavelength.
+    pub fn dispersive(ir: Ior) -> Self {
         Dielectric { ir }
     }
 
@@ -82,19 +187,20 @@ impl Dielectric {
     }
 }
 
-impl Material for Dielectric {
-    fn scatter(
+impl Dielectric {
+    /// Scatters with `u` in [0, 1) choosing between reflection and refraction.
+    fn scatter_with(
         &self,
         ray_in: &Ray,
         rec: &HitRecord,
         attenuation: &mut color::Color,
-    ) -> Option<Ray> {
+        u: f64,
+    ) -> Ray {
         *attenuation = Vec3(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
+        let ir = self
+            .ir
+            .at(ray_in.wavelength.unwrap_or(Ior::REFERENCE_WAVELENGTH));
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
 
         let unit_dir = ray_in.dir().unit_vector();
         let cos_theta = clamp((-unit_dir).dot(&rec.normal), -1.0, 1.0);
@@ -102,14 +208,362 @@ impl Material for Dielectric {
 
         let cannot_refract = (refraction_ratio * sin_theta) > 1.0;
         let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) < random_f64()
+            || Dielectric::reflectance(cos_theta, refraction_ratio) < u
         {
             Vec3::reflect(&unit_dir, &rec.normal)
         } else {
             Vec3::refract(&unit_dir, &rec.normal, refraction_ratio)
         };
 
-        Some(Ray::new(rec.p, direction))
+        Ray::new(rec.p, direction)
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+    ) -> Option<Ray> {
+        Some(self.scatter_with(ray_in, rec, attenuation, random_f64()))
+    }
+
+    fn scatter_sampled(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+        sample: (f64, f64),
+    ) -> Option<Ray> {
+        Some(self.scatter_with(ray_in, rec, attenuation, sample.0))
+    }
+}
+
+/* ============================================= */
+/// A principled ("Disney") uber material.
+///
+/// Every parameter is in [0, 1] except `ior` and `emission`. A lobe is picked
+/// stochastically on each scatter (transmission, clearcoat, specular or
+/// diffuse + sheen) and delegates to the `Lambertian`, `Metal` and
+/// `Dielectric` building blocks above.
+pub struct Principled {
+    pub base_color: color::Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub transmission: f64,
+    pub ior: f64,
+    pub emission: color::Color,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            base_color: Vec3(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            ior: 1.45,
+            emission: Vec3(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl Principled {
+    pub fn new(base_color: color::Color) -> Self {
+        Principled {
+            base_color,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    fn lerp(a: color::Color, b: color::Color, t: f64) -> color::Color {
+        (1.0 - t) * a + t * b
+    }
+
+    #[inline]
+    fn schlick_weight(cosine: f64) -> f64 {
+        f64::powi(1.0 - clamp(cosine, 0.0, 1.0), 5)
+    }
+
+    /// Base color with its luminance divided out, used for the tint parameters.
+    fn tint(&self) -> color::Color {
+        let c = self.base_color;
+        let lum = 0.3 * c.0 + 0.6 * c.1 + 0.1 * c.2;
+        if lum > 0.0 {
+            c / lum
+        } else {
+            Vec3(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+    ) -> Option<Ray> {
+        let white = Vec3(1.0, 1.0, 1.0);
+        let cosine = (-ray_in.dir().unit_vector()).dot(&rec.normal);
+        let fw = Principled::schlick_weight(cosine);
+
+        // Transmission only exists for the dielectric part of the material.
+        let transmission = self.transmission * (1.0 - self.metallic);
+        if random_f64() < transmission {
+            let mut glass_attenuation = white;
+            let refracted =
+                Dielectric::new(self.ior).scatter(ray_in, rec, &mut glass_attenuation)?;
+            *attenuation = self.base_color;
+            // Roughening mustn't push the ray back over to the side it came
+            // from (or through, for a reflection): resample when it does.
+            let side = refracted.dir().dot(&rec.normal) > 0.0;
+            let dir = (0..8)
+                .map(|_| refracted.dir() + self.roughness * Vec3::random_in_unit_sphere())
+                .find(|dir| (dir.dot(&rec.normal) > 0.0) == side)
+                .unwrap_or_else(|| refracted.dir());
+            return Some(Ray::new(refracted.orig(), dir));
+        }
+
+        // Clearcoat: a colourless, fixed IOR 1.5 layer on top of everything else.
+        let clearcoat = 0.25 * self.clearcoat * (0.04 + 0.96 * fw);
+        if random_f64() < clearcoat {
+            return Metal::new(white, self.clearcoat_roughness).scatter(ray_in, rec, attenuation);
+        }
+
+        let dielectric_f0 =
+            0.08 * self.specular * Principled::lerp(white, self.tint(), self.specular_tint);
+        let f0 = Principled::lerp(dielectric_f0, self.base_color, self.metallic);
+        let fresnel = f0 + fw * (white - f0);
+        let specular =
+            self.metallic + (1.0 - self.metallic) * (fresnel.0 + fresnel.1 + fresnel.2) / 3.0;
+        if random_f64() < specular {
+            let tint = if self.metallic > 0.0 {
+                fresnel / specular
+            } else {
+                white
+            };
+            let reflected = Metal::new(white, self.roughness * self.roughness).scatter(
+                ray_in,
+                rec,
+                attenuation,
+            )?;
+            *attenuation = Principled::lerp(white, tint, self.metallic);
+            return Some(reflected);
+        }
+
+        let sheen = self.sheen * fw * Principled::lerp(white, self.tint(), self.sheen_tint);
+        let scattered = Lambertian::new(self.base_color).scatter(ray_in, rec, attenuation)?;
+        *attenuation += sheen;
+        Some(scattered)
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> color::Color {
+        self.emission
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> color::Color {
+        self.base_color
+    }
+}
+
+/* ============================================= */
+/// Wraps a material, replacing its shading normal with one read from a
+/// tangent-space normal map before scattering.
+pub struct NormalMapped {
+    inner: Rc<dyn Material>,
+    map: Rc<dyn Texture>,
+}
+
+impl NormalMapped {
+    pub fn new(inner: &Rc<dyn Material>, map: &Rc<dyn Texture>) -> Self {
+        NormalMapped {
+            inner: inner.clone(),
+            map: map.clone(),
+        }
+    }
+
+    fn perturb(&self, rec: &HitRecord) -> HitRecord {
+        let n = rec.normal;
+        let tangent = (rec.dpdu - n.dot(&rec.dpdu) * n).unit_vector();
+        let mut bitangent = n.cross(tangent);
+        if bitangent.dot(&rec.dpdv) < 0.0 {
+            bitangent = -bitangent;
+        }
+
+        // Texel values in [0, 1] encode components in [-1, 1].
+        let m = 2.0 * self.map.value(rec.u, rec.v, &rec.p) - Vec3(1.0, 1.0, 1.0);
+        let mut perturbed = rec.clone();
+        perturbed.normal = (m.x() * tangent + m.y() * bitangent + m.z() * n).unit_vector();
+        perturbed
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+    ) -> Option<Ray> {
+        self.inner.scatter(ray_in, &self.perturb(rec), attenuation)
+    }
+
+    fn scatter_sampled(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+        sample: (f64, f64),
+    ) -> Option<Ray> {
+        self.inner
+            .scatter_sampled(ray_in, &self.perturb(rec), attenuation, sample)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> color::Color {
+        self.inner.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> color::Color {
+        self.inner.albedo(rec)
+    }
+}
+
+/* ============================================= */
+/// Wraps a material, displacing its shading normal by the gradient of a
+/// scalar height texture (the average of its channels) times `scale`.
+pub struct BumpMapped {
+    inner: Rc<dyn Material>,
+    height: Rc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMapped {
+    pub fn new(inner: &Rc<dyn Material>, height: &Rc<dyn Texture>, scale: f64) -> Self {
+        BumpMapped {
+            inner: inner.clone(),
+            height: height.clone(),
+            scale,
+        }
+    }
+
+    fn height_at(&self, u: f64, v: f64, rec: &HitRecord) -> f64 {
+        let c = self.height.value(u, v, &rec.p);
+        self.scale * (c.0 + c.1 + c.2) / 3.0
+    }
+
+    fn perturb(&self, rec: &HitRecord) -> HitRecord {
+        const DELTA: f64 = 0.0005;
+        let h = self.height_at(rec.u, rec.v, rec);
+        let dhdu = (self.height_at(rec.u + DELTA, rec.v, rec) - h) / DELTA;
+        let dhdv = (self.height_at(rec.u, rec.v + DELTA, rec) - h) / DELTA;
+
+        let n = rec.normal;
+        let dpdu = rec.dpdu + dhdu * n;
+        let dpdv = rec.dpdv + dhdv * n;
+        let mut bumped = dpdu.cross(dpdv).unit_vector();
+        if bumped.dot(&n) < 0.0 {
+            bumped = -bumped;
+        }
+
+        let mut perturbed = rec.clone();
+        perturbed.normal = bumped;
+        perturbed
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+    ) -> Option<Ray> {
+        self.inner.scatter(ray_in, &self.perturb(rec), attenuation)
+    }
+
+    fn scatter_sampled(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+        sample: (f64, f64),
+    ) -> Option<Ray> {
+        self.inner
+            .scatter_sampled(ray_in, &self.perturb(rec), attenuation, sample)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> color::Color {
+        self.inner.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> color::Color {
+        self.inner.albedo(rec)
+    }
+}
+
+/* ============================================= */
+/// Wraps a material, multiplying everything it reflects and emits by `tint`.
+pub struct Tinted {
+    inner: Rc<dyn Material>,
+    tint: color::Color,
+}
+
+impl Tinted {
+    pub fn new(inner: &Rc<dyn Material>, tint: color::Color) -> Self {
+        Tinted {
+            inner: inner.clone(),
+            tint,
+        }
+    }
+}
+
+impl Material for Tinted {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+    ) -> Option<Ray> {
+        let scattered = self.inner.scatter(ray_in, rec, attenuation)?;
+        *attenuation = self.tint * *attenuation;
+        Some(scattered)
+    }
+
+    fn scatter_sampled(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut color::Color,
+        sample: (f64, f64),
+    ) -> Option<Ray> {
+        let scattered = self
+            .inner
+            .scatter_sampled(ray_in, rec, attenuation, sample)?;
+        *attenuation = self.tint * *attenuation;
+        Some(scattered)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> color::Color {
+        self.tint * self.inner.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> color::Color {
+        self.tint * self.inner.albedo(rec)
     }
 }
 
@@ -138,3 +592,35 @@ impl From<Dielectric> for Rc<dyn Material> {
         trait_object
     }
 }
+
+impl From<Principled> for Rc<dyn Material> {
+    #[inline]
+    fn from(material: Principled) -> Self {
+        let trait_object: Rc<dyn Material> = Rc::new(material);
+        trait_object
+    }
+}
+
+impl From<NormalMapped> for Rc<dyn Material> {
+    #[inline]
+    fn from(material: NormalMapped) -> Self {
+        let trait_object: Rc<dyn Material> = Rc::new(material);
+        trait_object
+    }
+}
+
+impl From<BumpMapped> for Rc<dyn Material> {
+    #[inline]
+    fn from(material: BumpMapped) -> Self {
+        let trait_object: Rc<dyn Material> = Rc::new(material);
+        trait_object
+    }
+}
+
+impl From<Tinted> for Rc<dyn Material> {
+    #[inline]
+    fn from(material: Tinted) -> Self {
+        let trait_object: Rc<dyn Material> = Rc::new(material);
+        trait_object
+    }
+}
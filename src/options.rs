@@ -0,0 +1,451 @@
+use crate::camera::{PhysicalCamera, Projection};
+use crate::curve::CurveType;
+use crate::denoise::DenoiseSettings;
+use crate::distortion::{parse_coefficients, LensEffects};
+use crate::film::CropWindow;
+use crate::filter::FilterKind;
+use crate::sampler::SamplerKind;
+use crate::stereo::{Convergence, StereoLayout, StereoRig};
+use crate::tonemap::DisplayTransform;
+
+const ASPECT_RATIO: f64 = 4.0 / 3.0;
+const IMG_WIDTH: i32 = 800;
+const IMG_HEIGHT: i32 = (IMG_WIDTH as f64 / ASPECT_RATIO) as i32;
+
+/// Render settings gathered from the command line.
+pub struct RenderOptions {
+    /// Image size in pixels.
+    pub width: i32,
+    pub height: i32,
+    /// Render only this part of the image, when set.
+    pub crop: Option<CropWindow>,
+    /// Save the whole image with only the crop window filled in, rather than
+    /// just the crop window.
+    pub crop_full: bool,
+    /// Projection of the camera the scene is viewed through.
+    pub projection: Projection,
+    /// Field of view in degrees, overriding the scene's. Fisheyes default to 180.
+    pub fov: Option<f64>,
+    /// Render a stereo pair into one image, when set.
+    pub stereo: Option<StereoRig>,
+    /// Photographic camera settings, setting the field of view, aperture,
+    /// shutter interval and exposure when given.
+    pub physical: Option<PhysicalCamera>,
+    /// Lens prescription to look through instead of a thin lens.
+    pub lens: Option<String>,
+    /// Distortion, chromatic aberration and vignetting of the lens, when set.
+    pub lens_effects: Option<LensEffects>,
+    /// Number of diaphragm blades, for a polygonal aperture.
+    pub blades: Option<u32>,
+    /// Rotation of the blades in degrees.
+    pub blade_rotation: f64,
+    /// Grayscale PPM image of the aperture, overriding the blades.
+    pub aperture_mask: Option<String>,
+    /// Cat's eye vignetting strength, see `Aperture::cat_eye`.
+    pub cat_eye: f64,
+    /// Trace one wavelength per path instead of RGB triples.
+    pub spectral: bool,
+    /// Sample pattern used for the pixel, lens and BSDF dimensions.
+    pub sampler: SamplerKind,
+    /// Reconstruction filter samples are splatted to the film with.
+    pub filter: FilterKind,
+    /// Filter radius in pixels, the filter's usual radius when not set.
+    pub filter_radius: Option<f64>,
+    /// Samples per pixel, the maximum when sampling adaptively.
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    /// Keep sampling only the pixels that haven't converged yet.
+    pub adaptive: bool,
+    /// Samples every pixel gets before its error is estimated.
+    pub min_samples: u32,
+    /// Samples added to each unconverged pixel per adaptive pass.
+    pub adaptive_step: u32,
+    /// Relative error of the pixel mean below which a pixel is converged.
+    pub adaptive_threshold: f64,
+    /// Where to save the samples-per-pixel map, if anywhere.
+    pub sample_map: Option<String>,
+    /// Exposure, tone mapping and encoding applied to the saved images.
+    pub display: DisplayTransform,
+    /// Denoise the saved images, when set.
+    pub denoise: Option<DenoiseSettings>,
+    /// Save the AOVs as `<prefix>_<buffer>.pfm` files.
+    pub aov_prefix: Option<String>,
+    /// Where to save the image, stdout when not set.
+    pub output: Option<String>,
+    /// Render in passes of one sample per pixel, saving snapshots as it goes.
+    pub progressive: bool,
+    /// Save a snapshot every this many passes...
+    pub snapshot_passes: u32,
+    /// ...or after this many seconds, whichever comes first.
+    pub snapshot_seconds: f64,
+    /// Keyframes applied to the scene, see `Animation::parse`.
+    pub animation: Option<String>,
+    /// Render this range of animation frames, both ends included, to the
+    /// output path with the frame number filled in.
+    pub frames: Option<(u32, u32)>,
+    /// Which scene to build.
+    pub scene: String,
+    /// Groom added to the scene as hair, see `Curve::load`.
+    pub curves: Option<String>,
+    /// How the groom's curves are shaped.
+    pub curve_type: CurveType,
+    /// Seed for the random number generator the scene is built with. Picked
+    /// at random (or taken from the checkpoint being resumed) when not set.
+    pub seed: Option<u64>,
+    /// Where to save checkpoints, taken along with the snapshots.
+    pub checkpoint: Option<String>,
+    /// Checkpoint to resume the render from.
+    pub resume: Option<String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            width: IMG_WIDTH,
+            height: IMG_HEIGHT,
+            crop: None,
+            crop_full: false,
+            projection: Projection::Perspective,
+            fov: None,
+            stereo: None,
+            physical: None,
+            lens: None,
+            lens_effects: None,
+            blades: None,
+            blade_rotation: 0.0,
+            aperture_mask: None,
+            cat_eye: 0.0,
+            spectral: false,
+            sampler: SamplerKind::Sobol,
+            filter: FilterKind::Box,
+            filter_radius: None,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            adaptive: false,
+            min_samples: 16,
+            adaptive_step: 8,
+            adaptive_threshold: 0.01,
+            sample_map: None,
+            display: DisplayTransform::default(),
+            denoise: None,
+            aov_prefix: None,
+            output: None,
+            progressive: false,
+            snapshot_passes: 8,
+            snapshot_seconds: 30.0,
+            animation: None,
+            frames: None,
+            scene: "random".to_string(),
+            curves: None,
+            curve_type: CurveType::Ribbon(None),
+            seed: None,
+            checkpoint: None,
+            resume: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn from_args() -> Self {
+        RenderOptions::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Self {
+        let mut opts = RenderOptions::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--width" => opts.width = parse_value(&arg, args.next()),
+                "--height" => opts.height = parse_value(&arg, args.next()),
+                "--crop" => opts.crop = Some(parse_value(&arg, args.next())),
+                "--crop-full" => opts.crop_full = true,
+                "--projection" => opts.projection = parse_value(&arg, args.next()),
+                "--fov" => opts.fov = Some(parse_value(&arg, args.next())),
+                "--stereo" => {
+                    let layout = parse_value(&arg, args.next());
+                    opts.stereo_rig().layout = layout;
+                }
+                "--interocular" => {
+                    let interocular = parse_value(&arg, args.next());
+                    opts.stereo_rig().interocular = interocular;
+                }
+                "--convergence" => {
+                    let dist = parse_value(&arg, args.next());
+                    opts.stereo_rig().convergence_dist = Some(dist);
+                }
+                "--toe-in" => opts.stereo_rig().convergence = Convergence::ToeIn,
+                "--sensor-width" => {
+                    opts.physical_camera().sensor_width = parse_value(&arg, args.next())
+                }
+                "--focal-length" => {
+                    opts.physical_camera().focal_length = parse_value(&arg, args.next())
+                }
+                "--f-stop" => opts.physical_camera().f_stop = parse_value(&arg, args.next()),
+                "--shutter" => {
+                    let shutter: String = parse_value(&arg, args.next());
+                    opts.physical_camera().shutter = parse_seconds(&arg, &shutter);
+                }
+                "--iso" => opts.physical_camera().iso = parse_value(&arg, args.next()),
+                "--lens" => opts.lens = Some(parse_value(&arg, args.next())),
+                "--distortion" => opts.lens_effects().distortion = parse_value(&arg, args.next()),
+                "--chromatic-aberration" => {
+                    let scales = parse_list(&arg, args.next(), 2, 1.0);
+                    opts.lens_effects().chromatic_aberration = (scales[0], scales[1]);
+                }
+                "--vignetting" => {
+                    let coefficients = parse_list(&arg, args.next(), 3, 0.0);
+                    opts.lens_effects().vignetting =
+                        [coefficients[0], coefficients[1], coefficients[2]];
+                }
+                "--blades" => opts.blades = Some(parse_value(&arg, args.next())),
+                "--blade-rotation" => opts.blade_rotation = parse_value(&arg, args.next()),
+                "--aperture-mask" => opts.aperture_mask = Some(parse_value(&arg, args.next())),
+                "--cat-eye" => opts.cat_eye = parse_value(&arg, args.next()),
+                "--spectral" => opts.spectral = true,
+                "--sampler" => opts.sampler = parse_value(&arg, args.next()),
+                "--filter" => opts.filter = parse_value(&arg, args.next()),
+                "--filter-radius" => opts.filter_radius = Some(parse_value(&arg, args.next())),
+                "--spp" => opts.samples_per_pixel = parse_value(&arg, args.next()),
+                "--max-depth" => opts.max_depth = parse_value(&arg, args.next()),
+                "--adaptive" => opts.adaptive = true,
+                "--min-spp" => opts.min_samples = parse_value(&arg, args.next()),
+                "--adaptive-step" => opts.adaptive_step = parse_value(&arg, args.next()),
+                "--threshold" => opts.adaptive_threshold = parse_value(&arg, args.next()),
+                "--spp-map" => opts.sample_map = Some(parse_value(&arg, args.next())),
+                "--exposure" => opts.display.exposure = parse_value(&arg, args.next()),
+                "--tonemap" => opts.display.tone_map = parse_value(&arg, args.next()),
+                "--white-point" => opts.display.white_point = parse_value(&arg, args.next()),
+                "--gamma" => opts.display.gamma = Some(parse_value(&arg, args.next())),
+                "--denoise" => {
+                    opts.denoise.get_or_insert_with(DenoiseSettings::default);
+                }
+                "--denoise-iterations" => {
+                    let iterations = parse_value(&arg, args.next());
+                    opts.denoise
+                        .get_or_insert_with(DenoiseSettings::default)
+                        .iterations = iterations;
+                }
+                "--aov" => opts.aov_prefix = Some(parse_value(&arg, args.next())),
+                "--output" | "-o" => opts.output = Some(parse_value(&arg, args.next())),
+                "--progressive" => opts.progressive = true,
+                "--snapshot-passes" => opts.snapshot_passes = parse_value(&arg, args.next()),
+                "--snapshot-seconds" => opts.snapshot_seconds = parse_value(&arg, args.next()),
+                "--animation" => opts.animation = Some(parse_value(&arg, args.next())),
+                "--frames" => {
+                    let frames: String = parse_value(&arg, args.next());
+                    opts.frames = Some(parse_frames(&arg, &frames));
+                }
+                "--scene" => opts.scene = parse_value(&arg, args.next()),
+                "--curves" => opts.curves = Some(parse_value(&arg, args.next())),
+                "--curve-type" => opts.curve_type = parse_value(&arg, args.next()),
+                "--seed" => opts.seed = Some(parse_value(&arg, args.next())),
+                "--checkpoint" => opts.checkpoint = Some(parse_value(&arg, args.next())),
+                "--resume" => opts.resume = Some(parse_value(&arg, args.next())),
+                _ => eprintln!("Ignoring unknown argument: {}", arg),
+            }
+        }
+        if opts.width <= 0 || opts.height <= 0 || opts.width.checked_mul(opts.height).is_none() {
+            eprintln!("Image size {}x{} out of range", opts.width, opts.height);
+            std::process::exit(2);
+        }
+        match opts.stereo.as_ref().map(|rig| rig.layout) {
+            Some(StereoLayout::SideBySide) if opts.width % 2 != 0 => {
+                eprintln!("Side by side stereo needs an even width");
+                std::process::exit(2);
+            }
+            Some(StereoLayout::TopBottom) if opts.height % 2 != 0 => {
+                eprintln!("Top-bottom stereo needs an even height");
+                std::process::exit(2);
+            }
+            _ => {}
+        }
+        if opts.adaptive_step == 0 {
+            eprintln!("--adaptive-step must be at least 1");
+            std::process::exit(2);
+        }
+        if std::time::Duration::try_from_secs_f64(opts.snapshot_seconds).is_err() {
+            eprintln!("--snapshot-seconds must be a non-negative number of seconds");
+            std::process::exit(2);
+        }
+        if let Some(ref effects) = opts.lens_effects {
+            let (red, blue) = effects.chromatic_aberration;
+            if !(red > 0.0 && red.is_finite() && blue > 0.0 && blue.is_finite()) {
+                eprintln!("--chromatic-aberration scales must be positive and finite");
+                std::process::exit(2);
+            }
+        }
+        if let Some(crop) = opts.crop {
+            if !crop.fits(opts.width, opts.height) {
+                eprintln!(
+                    "Crop window {}x{} at ({}, {}) doesn't fit in the {}x{} image",
+                    crop.width, crop.height, crop.x, crop.y, opts.width, opts.height
+                );
+                std::process::exit(2);
+            }
+        }
+        opts
+    }
+}
+
+impl RenderOptions {
+    /// Whether the render is done in passes, checkpointing needs them.
+    pub fn is_progressive(&self) -> bool {
+        self.progressive || self.checkpoint.is_some() || self.resume.is_some()
+    }
+
+    /// The stereo rig, set up with a side-by-side off-axis pair if there
+    /// wasn't one yet.
+    fn stereo_rig(&mut self) -> &mut StereoRig {
+        self.stereo.get_or_insert(StereoRig {
+            layout: StereoLayout::SideBySide,
+            interocular: 0.065,
+            convergence_dist: None,
+            convergence: Convergence::OffAxis,
+        })
+    }
+
+    /// The photographic settings, starting from a 50mm f/8 lens at 1/60 s
+    /// and ISO 100 if there weren't any yet.
+    fn physical_camera(&mut self) -> &mut PhysicalCamera {
+        self.physical.get_or_insert_with(PhysicalCamera::default)
+    }
+
+    /// The lens effects, starting from a perfect lens if there weren't any yet.
+    fn lens_effects(&mut self) -> &mut LensEffects {
+        self.lens_effects.get_or_insert_with(LensEffects::default)
+    }
+
+    /// Whether the first hits of camera rays need recording.
+    pub fn wants_aovs(&self) -> bool {
+        self.aov_prefix.is_some() || self.denoise.is_some()
+    }
+
+    /// Fingerprint of everything that affects the rendered image, so a
+    /// checkpoint is only resumed into the render it was taken from. Files
+    /// are fingerprinted by their contents. Output paths and snapshot
+    /// intervals are left out on purpose.
+    pub fn settings_hash(&self, seed: u64) -> u64 {
+        let settings = format!(
+            "{}|{}|{:?}|{:?}|{}|{}|{}x{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{}|{:?}|{}|{}|{:?}|{:?}|{:?}|{}|{}|{}|{}|{}|{}",
+            env!("CARGO_PKG_VERSION"),
+            self.scene,
+            file_hash(&self.animation),
+            file_hash(&self.curves),
+            self.curve_type,
+            seed,
+            self.width,
+            self.height,
+            self.crop,
+            self.projection,
+            self.fov,
+            self.stereo,
+            self.physical,
+            file_hash(&self.lens),
+            self.lens_effects,
+            self.blades,
+            self.blade_rotation,
+            file_hash(&self.aperture_mask),
+            self.cat_eye,
+            self.spectral,
+            self.sampler,
+            self.filter,
+            self.filter_radius,
+            self.samples_per_pixel,
+            self.max_depth,
+            self.adaptive,
+            self.min_samples,
+            self.adaptive_step,
+            self.adaptive_threshold,
+        );
+        fnv1a(settings.as_bytes())
+    }
+}
+
+/// FNV-1a, stable across platforms and compiler versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Hash of the contents of the file at `path`, so editing a file the render
+/// reads counts as changing its settings. `None` without a file, or when it
+/// can't be read.
+fn file_hash(path: &Option<String>) -> Option<u64> {
+    let bytes = std::fs::read(path.as_ref()?).ok()?;
+    Some(fnv1a(&bytes))
+}
+
+/// Parses the value following `flag`, exiting with a message when it's
+/// missing or malformed.
+fn parse_value<T>(flag: &str, value: Option<String>) -> T
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = value.unwrap_or_else(|| {
+        eprintln!("Missing value for {}", flag);
+        std::process::exit(2);
+    });
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("Invalid value {:?} for {}: {}", value, flag, e);
+        std::process::exit(2);
+    })
+}
+
+/// Parses a comma separated list of up to `len` numbers, padding it with
+/// `fill`, exiting with a message when it's missing or malformed.
+fn parse_list(flag: &str, value: Option<String>, len: usize, fill: f64) -> Vec<f64> {
+    let value: String = parse_value(flag, value);
+    match parse_coefficients(&value) {
+        Ok(mut list) if list.len() <= len => {
+            list.resize(len, fill);
+            list
+        }
+        Ok(_) => {
+            eprintln!(
+                "Invalid value {:?} for {}: expected at most {} numbers",
+                value, flag, len
+            );
+            std::process::exit(2);
+        }
+        Err(e) => {
+            eprintln!("Invalid value {:?} for {}: {}", value, flag, e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Parses a frame range `first-last`, or a single frame.
+fn parse_frames(flag: &str, value: &str) -> (u32, u32) {
+    let range = match value.split_once('-') {
+        Some((first, last)) => first.parse().and_then(|f| last.parse().map(|l| (f, l))),
+        None => value.parse().map(|f| (f, f)),
+    };
+    match range {
+        Ok((first, last)) if first <= last => (first, last),
+        _ => {
+            eprintln!(
+                "Invalid value {:?} for {}: expected a frame range like 0-47",
+                value, flag
+            );
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Parses a duration in seconds, either as a number or a fraction like `1/60`.
+fn parse_seconds(flag: &str, value: &str) -> f64 {
+    let seconds = match value.split_once('/') {
+        Some((num, den)) => num
+            .parse::<f64>()
+            .and_then(|n| den.parse::<f64>().map(|d| n / d)),
+        None => value.parse(),
+    };
+    match seconds {
+        Ok(s) if s > 0.0 => s,
+        _ => {
+            eprintln!("Invalid value {:?} for {}: expected seconds", value, flag);
+            std::process::exit(2);
+        }
+    }
+}
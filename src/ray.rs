@@ -4,11 +4,20 @@ use crate::vec3::*;
 pub struct Ray {
     pub orig: Point3,
     pub dir: Vec3,
+    /// Wavelength in nanometres carried by the ray in spectral mode.
+    pub wavelength: Option<f64>,
+    /// Time in seconds the ray is sent at, for motion blur.
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(orig: Point3, dir: Vec3) -> Self {
-        Ray { orig, dir }
+        Ray {
+            orig,
+            dir,
+            wavelength: None,
+            time: 0.0,
+        }
     }
     pub fn orig(&self) -> Point3 {
         self.orig.clone()
@@ -0,0 +1,269 @@
+use crate::aov::AovSample;
+use crate::camera::Camera;
+use crate::film::{Film, PixelStats};
+use crate::filter::Filter;
+use crate::hittable::Hittable;
+use crate::options::RenderOptions;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::stereo::StereoLayout;
+use crate::vec3::Vec3;
+use crate::{color::Color, ray_color, ray_color_spectral, sky_color, spectrum, INFINITY};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Everything a frame is rendered from.
+pub struct Renderer<'a, H: Hittable> {
+    pub world: &'a H,
+    pub cam: &'a dyn Camera,
+    pub opts: &'a RenderOptions,
+    filter: Box<dyn Filter>,
+}
+
+impl<'a, H: Hittable> Renderer<'a, H> {
+    pub fn new(world: &'a H, cam: &'a dyn Camera, opts: &'a RenderOptions) -> Self {
+        Renderer {
+            world,
+            cam,
+            opts,
+            filter: opts.filter.build(opts.filter_radius),
+        }
+    }
+
+    /// Whether adaptive sampling is done with a pixel. Always false without it.
+    fn converged(&self, pixel: &PixelStats) -> bool {
+        self.opts.adaptive
+            && pixel.count >= self.opts.min_samples
+            && pixel.relative_error() <= self.opts.adaptive_threshold
+    }
+
+    /// The columns and rows of pixels to render, all of them without a crop
+    /// window.
+    fn region(&self, width: i32, height: i32) -> (Range<i32>, Range<i32>) {
+        match self.opts.crop {
+            Some(ref crop) => crop.film_ranges(height),
+            None => (0..width, 0..height),
+        }
+    }
+
+    /// Film position (x, y), in pixels, in the camera's [0, 1) coordinates.
+    fn film_position(&self, x: f64, y: f64, film: &Film) -> (f64, f64) {
+        (x / film.width as f64, y / film.height as f64)
+    }
+
+    /// The pixels a sample taken in pixel (i, j) may be splatted onto: the
+    /// half of the image its eye fills for stereo pairs, so filters don't
+    /// blend the eyes across the seam, and the whole film otherwise. Never
+    /// outside the crop window, which would leave a fringe around it.
+    fn splat_region(&self, i: i32, j: i32, film: &Film) -> (Range<i32>, Range<i32>) {
+        let (w, h) = (film.width, film.height);
+        let (columns, rows) = match self.opts.stereo.as_ref().map(|rig| rig.layout) {
+            Some(StereoLayout::SideBySide) if i < w / 2 => (0..w / 2, 0..h),
+            Some(StereoLayout::SideBySide) => (w / 2..w, 0..h),
+            Some(StereoLayout::TopBottom) if j < h / 2 => (0..w, 0..h / 2),
+            Some(StereoLayout::TopBottom) => (0..w, h / 2..h),
+            None => (0..w, 0..h),
+        };
+        let (crop_columns, crop_rows) = self.region(w, h);
+        (
+            columns.start.max(crop_columns.start)..columns.end.min(crop_columns.end),
+            rows.start.max(crop_rows.start)..rows.end.min(crop_rows.end),
+        )
+    }
+
+    /// The camera ray through film position (u, v), for color channel
+    /// `channel` or all of them.
+    fn camera_ray(
+        &self,
+        (u, v): (f64, f64),
+        lens: (f64, f64),
+        time: f64,
+        channel: Option<usize>,
+    ) -> Option<Ray> {
+        let mut r = match channel {
+            Some(c) => self.cam.generate_channel_ray(u, v, lens, c)?,
+            None => self.cam.generate_ray(u, v, lens)?,
+        };
+        r.time = time;
+        Some(r)
+    }
+
+    /// When in the shutter interval a sample is taken. Without a physical
+    /// camera the shutter is open as long as the default one's.
+    fn sample_time(&self, sampler: &mut dyn Sampler) -> f64 {
+        let (open, close) = self.opts.physical.unwrap_or_default().shutter_interval();
+        open + (close - open) * sampler.get_1d()
+    }
+
+    fn trace(&self, mut r: Ray, sampler: &mut dyn Sampler) -> Color {
+        if self.opts.spectral {
+            let lambda = spectrum::sample_wavelength(sampler.get_1d());
+            r.wavelength = Some(lambda);
+            let radiance = ray_color_spectral(&r, self.world, sampler, self.opts.max_depth);
+            spectrum::wavelength_to_rgb(radiance, lambda)
+        } else {
+            ray_color(&r, self.world, sampler, self.opts.max_depth)
+        }
+    }
+
+    /// Records the first hit of a camera ray in the film's AOVs.
+    fn record_aovs(&self, i: i32, j: i32, r: Option<&Ray>, film: &mut Film) {
+        if let Some(ref mut aovs) = film.aovs {
+            let hit = r.and_then(|r| self.world.hit(r, 0.001, INFINITY));
+            let sample = match (r, hit) {
+                (_, Some(rec)) => aovs.hit_sample(&rec, self.cam.depth(rec.p)),
+                (Some(r), None) => AovSample::miss(sky_color(r)),
+                (None, None) => AovSample::miss(Vec3(0.0, 0.0, 0.0)),
+            };
+            aovs.add(i, j, sample);
+        }
+    }
+
+    /// Takes `count` more samples in pixel (i, j).
+    fn sample_pixel(&self, i: i32, j: i32, count: u32, film: &mut Film, sampler: &mut dyn Sampler) {
+        for _ in 0..count {
+            let index = film.pixel(i, j).count;
+            sampler.start_sample(i, j, index);
+            let (dx, dy) = sampler.get_2d();
+            let uv = self.film_position(i as f64 + dx, j as f64 + dy, film);
+            let lens = sampler.get_2d();
+            let time = self.sample_time(sampler);
+            let r = self.camera_ray(uv, lens, time, None);
+            self.record_aovs(i, j, r.as_ref(), film);
+            let color = if self.cam.is_chromatic() {
+                // Each channel is seen along its own ray, and keeps only its
+                // own part of what that ray brings back.
+                let mut rgb = [0.0; 3];
+                for (c, value) in rgb.iter_mut().enumerate() {
+                    if let Some(r) = self.camera_ray(uv, lens, time, Some(c)) {
+                        let traced = self.trace(r, sampler);
+                        *value = [traced.0, traced.1, traced.2][c];
+                    }
+                }
+                Vec3(rgb[0], rgb[1], rgb[2])
+            } else {
+                match r {
+                    Some(r) => self.trace(r, sampler),
+                    None => Vec3(0.0, 0.0, 0.0),
+                }
+            };
+            let color = self.cam.vignetting(uv.0, uv.1) * color;
+            let region = self.splat_region(i, j, film);
+            film.add_sample(i, j, (dx, dy), color, &*self.filter, region);
+        }
+    }
+
+    /// Renders the frame, or its crop window, into a film.
+    ///
+    /// Without adaptive sampling every pixel gets `samples_per_pixel` samples.
+    /// With it, every pixel first gets `min_samples`, then passes of
+    /// `adaptive_step` samples go to the pixels whose relative error is still
+    /// above `adaptive_threshold`, until they converge or reach
+    /// `samples_per_pixel`.
+    pub fn render(&self, width: i32, height: i32) -> Film {
+        let opts = self.opts;
+        let mut film = Film::new(width, height);
+        if opts.wants_aovs() {
+            film.enable_aovs();
+        }
+        let mut sampler = opts.sampler.build(opts.samples_per_pixel);
+
+        let first_pass = if opts.adaptive {
+            opts.min_samples.min(opts.samples_per_pixel)
+        } else {
+            opts.samples_per_pixel
+        };
+        let (columns, rows) = self.region(width, height);
+        for j in rows.clone().rev() {
+            eprintln!("Lines Remaining: {}", j - rows.start);
+            for i in columns.clone() {
+                self.sample_pixel(i, j, first_pass, &mut film, &mut *sampler);
+            }
+        }
+
+        if !opts.adaptive {
+            return film;
+        }
+        let mut pass = 1;
+        loop {
+            let mut active = 0;
+            for j in rows.clone() {
+                for i in columns.clone() {
+                    let pixel = film.pixel(i, j);
+                    if pixel.count >= opts.samples_per_pixel
+                        || pixel.relative_error() <= opts.adaptive_threshold
+                    {
+                        continue;
+                    }
+                    active += 1;
+                    let count = opts.adaptive_step.min(opts.samples_per_pixel - pixel.count);
+                    self.sample_pixel(i, j, count, &mut film, &mut *sampler);
+                }
+            }
+            eprintln!("Adaptive pass {}: {} pixels sampled", pass, active);
+            if active == 0 {
+                return film;
+            }
+            pass += 1;
+        }
+    }
+
+    /// Renders the frame progressively: one sample per pixel per pass,
+    /// calling `snapshot` with the film every `snapshot_passes` passes or
+    /// `snapshot_seconds` seconds, whichever comes first.
+    ///
+    /// Pass `n` brings every pixel of `film` up to `n` samples, so a film
+    /// restored from a checkpoint carries on from where it was left. Stops
+    /// after `samples_per_pixel` passes, or as soon as `stop` is set (e.g. by
+    /// Ctrl-C), returning the film as it is at that point. With adaptive
+    /// sampling, converged pixels are skipped after `min_samples`.
+    pub fn render_progressive(
+        &self,
+        mut film: Film,
+        stop: &AtomicBool,
+        mut snapshot: impl FnMut(&Film),
+    ) -> Film {
+        let opts = self.opts;
+        let (width, height) = (film.width, film.height);
+        if opts.wants_aovs() {
+            film.enable_aovs();
+        }
+        let mut sampler = opts.sampler.build(opts.samples_per_pixel);
+        let interval = Duration::from_secs_f64(opts.snapshot_seconds);
+        let mut last_snapshot = Instant::now();
+
+        let (columns, rows) = self.region(width, height);
+        let done = rows
+            .clone()
+            .flat_map(|j| columns.clone().map(move |i| (i, j)))
+            .map(|(i, j)| film.pixel(i, j))
+            .filter(|pixel| !self.converged(pixel))
+            .map(|pixel| pixel.count)
+            .min()
+            .unwrap_or(opts.samples_per_pixel);
+        'passes: for pass in done + 1..=opts.samples_per_pixel {
+            for j in rows.clone().rev() {
+                if stop.load(Ordering::Relaxed) {
+                    eprintln!("Interrupted during pass {}", pass);
+                    break 'passes;
+                }
+                for i in columns.clone() {
+                    let pixel = film.pixel(i, j);
+                    if pixel.count >= pass || self.converged(pixel) {
+                        continue;
+                    }
+                    self.sample_pixel(i, j, 1, &mut film, &mut *sampler);
+                }
+            }
+            eprintln!("Pass {}/{}", pass, opts.samples_per_pixel);
+
+            let every = opts.snapshot_passes.max(1);
+            if pass % every == 0 || last_snapshot.elapsed() >= interval {
+                snapshot(&film);
+                last_snapshot = Instant::now();
+            }
+        }
+        film
+    }
+}
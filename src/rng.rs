@@ -0,0 +1,58 @@
+use std::cell::Cell;
+
+/// State of the thread's random number generator (xoshiro256**). Kept as
+/// plain words so renders can be checkpointed and resumed exactly.
+pub type RngState = [u64; 4];
+
+thread_local! {
+    static STATE: Cell<RngState> = Cell::new(state_from_seed(rand::random()));
+}
+
+/// Expands a seed into a full state with SplitMix64, as recommended by the
+/// xoshiro authors.
+fn state_from_seed(seed: u64) -> RngState {
+    let mut x = seed;
+    let mut next = || {
+        x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = x;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    [next(), next(), next(), next()]
+}
+
+pub fn seed(seed: u64) {
+    STATE.with(|s| s.set(state_from_seed(seed)));
+}
+
+pub fn state() -> RngState {
+    STATE.with(|s| s.get())
+}
+
+pub fn set_state(state: RngState) {
+    STATE.with(|s| s.set(state));
+}
+
+#[inline]
+pub fn next_u64() -> u64 {
+    STATE.with(|cell| {
+        let mut s = cell.get();
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        cell.set(s);
+        result
+    })
+}
+
+/// Uniform real in [0, 1) from the top 53 bits.
+#[inline]
+pub fn next_f64() -> f64 {
+    (next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
@@ -0,0 +1,208 @@
+const EPSILON: f64 = 1e-9;
+
+#[inline]
+fn is_zero(x: f64) -> bool {
+    x.abs() < EPSILON
+}
+
+/// Real roots of `c[2] x² + c[1] x + c[0]`.
+pub fn solve_quadratic(c: [f64; 3]) -> Vec<f64> {
+    let p = c[1] / (2.0 * c[2]);
+    let q = c[0] / c[2];
+    let d = p * p - q;
+    if is_zero(d) {
+        vec![-p]
+    } else if d < 0.0 {
+        Vec::new()
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![sqrt_d - p, -sqrt_d - p]
+    }
+}
+
+/// Real roots of `c[3] x³ + c[2] x² + c[1] x + c[0]`.
+pub fn solve_cubic(c: [f64; 4]) -> Vec<f64> {
+    // Normal form x³ + Ax² + Bx + C, then x = y - A/3 gives y³ + 3py + 2q.
+    let a = c[2] / c[3];
+    let b = c[1] / c[3];
+    let cc = c[0] / c[3];
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + cc) / 2.0;
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let mut roots = if is_zero(d) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        // Three real roots, the casus irreducibilis.
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + std::f64::consts::PI / 3.0).cos(),
+            -t * (phi - std::f64::consts::PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+    roots
+}
+
+/// Real roots of `c[4] x⁴ + c[3] x³ + c[2] x² + c[1] x + c[0]`, by Ferrari's
+/// method as in Schwarze's "Cubic and Quartic Roots" (Graphics Gems). Nearly
+/// double roots can come out a little off, so callers that need precision
+/// should polish them, see `polish`.
+pub fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    // Normal form x⁴ + Ax³ + Bx² + Cx + D, then x = y - A/4 gives
+    // y⁴ + py² + qy + r.
+    let a = c[3] / c[4];
+    let b = c[2] / c[4];
+    let cc = c[1] / c[4];
+    let d = c[0] / c[4];
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + cc;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * cc / 4.0 + d;
+
+    let mut roots = if is_zero(r) {
+        // y (y³ + py + q) = 0
+        let mut roots = solve_cubic([q, p, 0.0, 1.0]);
+        roots.push(0.0);
+        roots
+    } else {
+        // Solve the resolvent cubic and use one of its roots to split the
+        // quartic into two quadratics.
+        let z = solve_cubic([r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let root = |x: f64| {
+            if is_zero(x) {
+                Some(0.0)
+            } else if x > 0.0 {
+                Some(x.sqrt())
+            } else {
+                None
+            }
+        };
+        match (root(u), root(v)) {
+            (Some(u), Some(v)) => {
+                let v = if q < 0.0 { -v } else { v };
+                let mut roots = solve_quadratic([z - u, v, 1.0]);
+                roots.extend(solve_quadratic([z + u, -v, 1.0]));
+                roots
+            }
+            _ => Vec::new(),
+        }
+    };
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+    }
+    roots
+}
+
+/// Refines a root of the polynomial with a few Newton steps.
+pub fn polish(c: &[f64], mut x: f64) -> f64 {
+    for _ in 0..4 {
+        let (mut value, mut slope) = (0.0, 0.0);
+        for &coefficient in c.iter().rev() {
+            slope = slope * x + value;
+            value = value * x + coefficient;
+        }
+        if slope == 0.0 {
+            break;
+        }
+        x -= value / slope;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable::Hittable, material::Lambertian, ray::Ray, torus::Torus, vec3::Vec3};
+
+    fn assert_roots(mut roots: Vec<f64>, expected: &[f64], tolerance: f64) {
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        roots.dedup_by(|a, b| (*a - *b).abs() < tolerance);
+        assert_eq!(roots.len(), expected.len(), "roots {:?}", roots);
+        for (root, want) in roots.iter().zip(expected) {
+            assert!((root - want).abs() < tolerance, "roots {:?}", roots);
+        }
+    }
+
+    #[test]
+    fn quadratic() {
+        // (x - 1)(x + 3)
+        assert_roots(solve_quadratic([-3.0, 2.0, 1.0]), &[-3.0, 1.0], 1e-12);
+        // (x - 2)²
+        assert_roots(solve_quadratic([4.0, -4.0, 1.0]), &[2.0], 1e-12);
+        assert_roots(solve_quadratic([1.0, 0.0, 1.0]), &[], 1e-12);
+    }
+
+    #[test]
+    fn cubic() {
+        // (x - 1)(x - 2)(x - 3)
+        assert_roots(solve_cubic([-6.0, 11.0, -6.0, 1.0]), &[1.0, 2.0, 3.0], 1e-9);
+        // (x - 1)²(x + 2)
+        assert_roots(solve_cubic([2.0, -3.0, 0.0, 1.0]), &[-2.0, 1.0], 1e-9);
+        // (x - 1)(x² + 1)
+        assert_roots(solve_cubic([-1.0, 1.0, -1.0, 1.0]), &[1.0], 1e-9);
+    }
+
+    #[test]
+    fn quartic() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let c = [24.0, -50.0, 35.0, -10.0, 1.0];
+        assert_roots(solve_quartic(c), &[1.0, 2.0, 3.0, 4.0], 1e-9);
+        // (x² + 1)(x² + 4)
+        assert_roots(solve_quartic([4.0, 0.0, 5.0, 0.0, 1.0]), &[], 1e-9);
+    }
+
+    #[test]
+    fn quartic_double_roots() {
+        // (x - 1)²(x - 3)², the shape a ray grazing a torus gives.
+        let c = [9.0, -24.0, 22.0, -8.0, 1.0];
+        let roots = solve_quartic(c)
+            .into_iter()
+            .map(|x| polish(&c, x))
+            .collect();
+        assert_roots(roots, &[1.0, 3.0], 1e-4);
+        // (x - 2)²(x + 1)(x + 5)
+        let c = [20.0, 4.0, -15.0, 2.0, 1.0];
+        let roots = solve_quartic(c)
+            .into_iter()
+            .map(|x| polish(&c, x))
+            .collect();
+        assert_roots(roots, &[-5.0, -1.0, 2.0], 1e-4);
+    }
+
+    #[test]
+    fn ray_grazing_torus() {
+        let torus = Torus::new(
+            Vec3(0.0, 0.0, 0.0),
+            2.0,
+            0.5,
+            &Lambertian::new(Vec3(0.5, 0.5, 0.5)).into(),
+        );
+        // Touches the top of the tube at x = -2 and x = 2.
+        let grazing = Ray::new(Vec3(-5.0, 0.5, 0.0), Vec3(1.0, 0.0, 0.0));
+        let rec = torus.hit(&grazing, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-3, "t = {}", rec.t);
+        assert!(rec.normal.y().abs() > 0.999);
+        // Just above the tube, and through the hole.
+        let above = Ray::new(Vec3(-5.0, 0.501, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert!(torus.hit(&above, 0.001, f64::INFINITY).is_none());
+        let hole = Ray::new(Vec3(0.0, 5.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        assert!(torus.hit(&hole, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,310 @@
+use crate::vec3::random_f64;
+use std::str::FromStr;
+
+/// Source of the sample values used by one path: the pixel position, the
+/// lens position, the wavelength and the BSDF dimensions of every bounce.
+///
+/// Samplers hand out consecutive dimensions of a sample vector, so the same
+/// call order must be kept for every sample of a pixel.
+pub trait Sampler {
+    /// Starts sample number `index` of pixel (x, y), resetting the dimension.
+    fn start_sample(&mut self, x: i32, y: i32, index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SamplerKind {
+    Random,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(SamplerKind::Random),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            _ => Err(format!("Unknown sampler: {}", s)),
+        }
+    }
+}
+
+impl SamplerKind {
+    pub fn build(self, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Random => Box::new(RandomSampler),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new()),
+            SamplerKind::Sobol => Box::new(SobolSampler::new()),
+        }
+    }
+}
+
+/* ========================================== */
+/// Hashes a list of words into one, used to decorrelate pixels and dimensions.
+#[inline]
+fn hash(words: &[u32]) -> u32 {
+    let mut h: u32 = 0x9e37_79b9;
+    for &w in words {
+        // Murmur3 style mixing.
+        let mut k = w.wrapping_mul(0xcc9e_2d51);
+        k = k.rotate_left(15).wrapping_mul(0x1b87_3593);
+        h ^= k;
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+#[inline]
+fn to_unit_f64(x: u32) -> f64 {
+    // Keeps the result strictly below one.
+    (x as f64 / 4294967296.0).min(1.0 - f64::EPSILON)
+}
+
+/// Element `i` of a random permutation of [0, l) selected by `p`
+/// (Kensler, "Correlated Multi-Jittered Sampling").
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    i.wrapping_add(p) % l
+}
+
+/* ========================================== */
+/// Independent uniform random samples, the original `random_f64` jitter.
+pub struct RandomSampler;
+
+impl Sampler for RandomSampler {
+    fn start_sample(&mut self, _x: i32, _y: i32, _index: u32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        random_f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (random_f64(), random_f64())
+    }
+}
+
+/* ========================================== */
+/// Jittered stratified samples. Each dimension is split into one stratum per
+/// sample (a grid for 2D), and every pixel and dimension visits the strata in
+/// its own random order.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    nx: u32,
+    ny: u32,
+    pixel_seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let nx = (samples_per_pixel as f64).sqrt().ceil() as u32;
+        let ny = samples_per_pixel.div_ceil(nx);
+        StratifiedSampler {
+            samples_per_pixel,
+            nx,
+            ny,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_seed(&mut self) -> u32 {
+        self.dimension += 1;
+        hash(&[self.pixel_seed, self.dimension])
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: i32, y: i32, index: u32) {
+        self.pixel_seed = hash(&[x as u32, y as u32]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let n = self.samples_per_pixel;
+        let seed = self.next_seed();
+        let stratum = permutation_element(self.index % n, n, seed);
+        (stratum as f64 + random_f64()) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let n = self.nx * self.ny;
+        let seed = self.next_seed();
+        let stratum = permutation_element(self.index % n, n, seed);
+        (
+            ((stratum % self.nx) as f64 + random_f64()) / self.nx as f64,
+            ((stratum / self.nx) as f64 + random_f64()) / self.ny as f64,
+        )
+    }
+}
+
+/* ========================================== */
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Radical inverse of `index` in `base`, mirroring its digits around the point.
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0.0;
+    while index > 0 {
+        let digit = index % base;
+        index /= base;
+        inv_base_n *= inv_base;
+        reversed = reversed * base as f64 + digit as f64;
+    }
+    (reversed * inv_base_n).min(1.0 - f64::EPSILON)
+}
+
+/// The Halton sequence, one prime base per dimension, shifted per pixel with a
+/// Cranley-Patterson rotation so neighbouring pixels don't share patterns.
+/// Dimensions past the table of primes fall back to random numbers.
+#[derive(Default)]
+pub struct HaltonSampler {
+    pixel_seed: u32,
+    index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new() -> Self {
+        HaltonSampler::default()
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: i32, y: i32, index: u32) {
+        self.pixel_seed = hash(&[x as u32, y as u32]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dim = self.dimension;
+        self.dimension += 1;
+        if dim >= PRIMES.len() {
+            return random_f64();
+        }
+        let shift = to_unit_f64(hash(&[self.pixel_seed, dim as u32]));
+        let x = radical_inverse(PRIMES[dim], self.index) + shift;
+        x - x.floor()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/* ========================================== */
+/// The 2D Sobol (0, 2)-sequence, with hash based Owen scrambling and index
+/// shuffling per pixel and dimension pair (Burley, "Practical Hash-based
+/// Owen Scrambling"). Samples are best with power of two counts.
+#[derive(Default)]
+pub struct SobolSampler {
+    pixel_seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new() -> Self {
+        SobolSampler::default()
+    }
+
+    fn sobol_2d(index: u32) -> (u32, u32) {
+        let x = index.reverse_bits();
+        let mut y = 0;
+        let mut v: u32 = 1 << 31;
+        let mut i = index;
+        while i != 0 {
+            if i & 1 != 0 {
+                y ^= v;
+            }
+            i >>= 1;
+            v ^= v >> 1;
+        }
+        (x, y)
+    }
+
+    /// Laine-Karras style hash, which only lets bits affect higher bits.
+    fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+        x = x.wrapping_add(seed);
+        x ^= x.wrapping_mul(0x6c50b47c);
+        x ^= x.wrapping_mul(0xb82f1e52);
+        x ^= x.wrapping_mul(0xc7afe638);
+        x ^= x.wrapping_mul(0x8d22f6e6);
+        x
+    }
+
+    fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+        SobolSampler::laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: i32, y: i32, index: u32) {
+        self.pixel_seed = hash(&[x as u32, y as u32]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.get_2d().0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = hash(&[self.pixel_seed, self.dimension]);
+        self.dimension += 1;
+        let index = SobolSampler::nested_uniform_scramble(self.index, seed);
+        let (x, y) = SobolSampler::sobol_2d(index);
+        (
+            to_unit_f64(SobolSampler::nested_uniform_scramble(x, hash(&[seed, 1]))),
+            to_unit_f64(SobolSampler::nested_uniform_scramble(y, hash(&[seed, 2]))),
+        )
+    }
+}
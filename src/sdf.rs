@@ -0,0 +1,319 @@
+use crate::{
+    aabb::Aabb,
+    clamp,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    sphere::Sphere,
+    vec3::*,
+};
+use std::rc::Rc;
+
+/// A signed distance function: negative inside the shape, positive outside,
+/// built as a tree of primitives and operations on them. Primitives are
+/// centered on the origin, `Translate` moves them.
+pub enum Sdf {
+    Sphere {
+        radius: f64,
+    },
+    /// A box reaching `half_size` out from the origin along each axis.
+    Box {
+        half_size: Vec3,
+    },
+    /// A torus lying flat around the Y axis.
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    /// The points within `radius` of the segment from `a` to `b`.
+    Capsule {
+        a: Point3,
+        b: Point3,
+        radius: f64,
+    },
+    Translate {
+        offset: Vec3,
+        inner: Box<Sdf>,
+    },
+    /// Both shapes, blended together over a distance of about `smoothness`.
+    /// Zero gives the plain union.
+    Union {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        smoothness: f64,
+    },
+    /// The first shape with the second one carved out of it, with the edges
+    /// rounded over a distance of about `smoothness`.
+    Subtraction {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        smoothness: f64,
+    },
+    /// Twists the shape around the Y axis by `rate` radians per unit of height.
+    Twist {
+        rate: f64,
+        inner: Box<Sdf>,
+    },
+    /// Repeats the shape endlessly on a grid with cells of size `period`.
+    /// Zero components leave that axis alone.
+    Repeat {
+        period: Vec3,
+        inner: Box<Sdf>,
+    },
+}
+
+impl Sdf {
+    pub fn translated(self, offset: Vec3) -> Sdf {
+        Sdf::Translate {
+            offset,
+            inner: Box::new(self),
+        }
+    }
+
+    pub fn twisted(self, rate: f64) -> Sdf {
+        Sdf::Twist {
+            rate,
+            inner: Box::new(self),
+        }
+    }
+
+    pub fn repeated(self, period: Vec3) -> Sdf {
+        Sdf::Repeat {
+            period,
+            inner: Box::new(self),
+        }
+    }
+
+    pub fn union(self, other: Sdf, smoothness: f64) -> Sdf {
+        Sdf::Union {
+            a: Box::new(self),
+            b: Box::new(other),
+            smoothness,
+        }
+    }
+
+    pub fn subtract(self, other: Sdf, smoothness: f64) -> Sdf {
+        Sdf::Subtraction {
+            a: Box::new(self),
+            b: Box::new(other),
+            smoothness,
+        }
+    }
+
+    /// Signed distance from `p` to the surface. Only exact for some trees,
+    /// but never more than `lipschitz` times the true distance.
+    pub fn distance(&self, p: Point3) -> f64 {
+        match self {
+            Sdf::Sphere { radius } => p.length() - radius,
+            Sdf::Box { half_size } => {
+                let q = Vec3(
+                    p.0.abs() - half_size.0,
+                    p.1.abs() - half_size.1,
+                    p.2.abs() - half_size.2,
+                );
+                let outside = Vec3(q.0.max(0.0), q.1.max(0.0), q.2.max(0.0)).length();
+                outside + q.0.max(q.1).max(q.2).min(0.0)
+            }
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let ring = (p.0 * p.0 + p.2 * p.2).sqrt() - major_radius;
+                (ring * ring + p.1 * p.1).sqrt() - minor_radius
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let (pa, ba) = (p - *a, *b - *a);
+                let h = clamp(pa.dot(&ba) / ba.length_squared(), 0.0, 1.0);
+                (pa - h * ba).length() - radius
+            }
+            Sdf::Translate { offset, inner } => inner.distance(p - *offset),
+            Sdf::Union { a, b, smoothness } => {
+                let (a, b, k) = (a.distance(p), b.distance(p), *smoothness);
+                if k <= 0.0 {
+                    return a.min(b);
+                }
+                // Polynomial smooth minimum.
+                let h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+                b + h * (a - b) - k * h * (1.0 - h)
+            }
+            Sdf::Subtraction { a, b, smoothness } => {
+                let (a, b, k) = (a.distance(p), b.distance(p), *smoothness);
+                if k <= 0.0 {
+                    return a.max(-b);
+                }
+                let h = clamp(0.5 - 0.5 * (a + b) / k, 0.0, 1.0);
+                a + h * (-b - a) + k * h * (1.0 - h)
+            }
+            Sdf::Twist { rate, inner } => {
+                let (s, c) = (rate * p.1).sin_cos();
+                inner.distance(Vec3(c * p.0 - s * p.2, p.1, s * p.0 + c * p.2))
+            }
+            Sdf::Repeat { period, inner } => {
+                let wrap = |x: f64, period: f64| {
+                    if period > 0.0 {
+                        x - period * (x / period).round()
+                    } else {
+                        x
+                    }
+                };
+                inner.distance(Vec3(
+                    wrap(p.0, period.0),
+                    wrap(p.1, period.1),
+                    wrap(p.2, period.2),
+                ))
+            }
+        }
+    }
+
+    /// How much faster than the true distance `distance` can change, for
+    /// points up to `reach` away from the Y axis. Twisting stretches space
+    /// the further out it is.
+    pub fn lipschitz(&self, reach: f64) -> f64 {
+        match self {
+            Sdf::Sphere { .. } | Sdf::Box { .. } | Sdf::Torus { .. } | Sdf::Capsule { .. } => 1.0,
+            Sdf::Translate { offset, inner } => {
+                inner.lipschitz(reach + (offset.0 * offset.0 + offset.2 * offset.2).sqrt())
+            }
+            Sdf::Union { a, b, .. } | Sdf::Subtraction { a, b, .. } => {
+                a.lipschitz(reach).max(b.lipschitz(reach))
+            }
+            Sdf::Twist { rate, inner } => {
+                inner.lipschitz(reach) * (1.0 + (rate * reach) * (rate * reach)).sqrt()
+            }
+            Sdf::Repeat { period, inner } => {
+                let cell = |x: f64| if x > 0.0 { x / 2.0 } else { reach };
+                inner.lipschitz(reach.min((cell(period.0).powi(2) + cell(period.2).powi(2)).sqrt()))
+            }
+        }
+    }
+}
+
+/* ========================================== */
+const MAX_STEPS: usize = 512;
+/// How close to the surface a march has to get to count as a hit.
+const SURFACE_EPSILON: f64 = 1e-4;
+
+/// A shape given by a signed distance function, rendered by sphere tracing:
+/// marching along the ray by the distance to the nearest surface until it's
+/// close enough to count as a hit. Traced only inside `bounds`, which also
+/// keeps endless repetitions finite.
+pub struct SdfObject {
+    sdf: Sdf,
+    bounds: Aabb,
+    /// Fraction of the distance each step is allowed to take, below one for
+    /// distance functions that overestimate.
+    step_scale: f64,
+    pub mat_ptr: Rc<dyn Material>,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, bounds: Aabb, m: &Rc<dyn Material>) -> Self {
+        let reach = bounds
+            .corners()
+            .iter()
+            .map(|c| (c.0 * c.0 + c.2 * c.2).sqrt())
+            .fold(0.0, f64::max);
+        SdfObject {
+            step_scale: 1.0 / sdf.lipschitz(reach),
+            sdf,
+            bounds,
+            mat_ptr: m.clone(),
+        }
+    }
+
+    /// The outward normal at `p`, the gradient of the distance by central
+    /// differences.
+    fn normal(&self, p: Point3) -> Vec3 {
+        const H: f64 = 1e-5;
+        let d = |offset: Vec3| self.sdf.distance(p + offset) - self.sdf.distance(p - offset);
+        Vec3(
+            d(Vec3(H, 0.0, 0.0)),
+            d(Vec3(0.0, H, 0.0)),
+            d(Vec3(0.0, 0.0, H)),
+        )
+        .unit_vector()
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (start, end) = self.bounds.hit(r, t_min, t_max)?;
+        let length = r.dir().length();
+        let mut t = start;
+        // A ray leaving the surface, as scattered rays do, starts within the
+        // shell, so it has to get out of it before a hit counts. Other rays
+        // can hit as soon as they get close, wherever they start marching.
+        let mut left_shell = self.sdf.distance(r.orig()).abs() >= SURFACE_EPSILON;
+        for _ in 0..MAX_STEPS {
+            // Marching by the absolute distance finds the way out of the
+            // shape too, for rays refracted into it.
+            let distance = self.sdf.distance(r.at(t)).abs();
+            if distance >= SURFACE_EPSILON {
+                left_shell = true;
+            } else if left_shell {
+                let p = r.at(t);
+                let mut rec = HitRecord {
+                    t,
+                    p,
+                    ..Default::default()
+                };
+                rec.set_normal_face(r, self.normal(p));
+                // Spherical coordinates around the middle of the bounds.
+                let center = 0.5 * (self.bounds.min + self.bounds.max);
+                Sphere::set_surface_coords(&mut rec, p - center);
+                rec.mat_ptr = Some(self.mat_ptr.clone());
+                return Some(rec);
+            }
+            t += self.step_scale * distance.max(SURFACE_EPSILON) / length;
+            if t > end {
+                break;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl std::convert::From<SdfObject> for Rc<dyn Hittable> {
+    fn from(object: SdfObject) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(object);
+        trait_object
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn ball() -> SdfObject {
+        SdfObject::new(
+            Sdf::Sphere { radius: 1.0 },
+            Aabb::around(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 1.0, 1.0)),
+            &Lambertian::new(Vec3(0.5, 0.5, 0.5)).into(),
+        )
+    }
+
+    #[test]
+    fn ray_starting_just_outside_hits() {
+        // The march starts within the shell, but the ray comes from afar.
+        let r = Ray::new(Vec3(0.0, 0.0, 3.0), Vec3(0.0, 0.0, -1.0));
+        let rec = ball()
+            .hit(&r, 2.0 - 0.5 * SURFACE_EPSILON, f64::INFINITY)
+            .unwrap();
+        assert!((rec.t - 2.0).abs() < SURFACE_EPSILON, "t = {}", rec.t);
+    }
+
+    #[test]
+    fn ray_leaving_the_surface_skips_it() {
+        let r = Ray::new(Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, 1.0));
+        assert!(ball().hit(&r, 0.001, f64::INFINITY).is_none());
+        // Refracted into the ball, it finds the far side.
+        let r = Ray::new(Vec3(0.0, 0.0, 1.0), Vec3(0.0, 0.0, -1.0));
+        let rec = ball().hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-3, "t = {}", rec.t);
+    }
+}
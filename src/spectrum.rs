@@ -0,0 +1,84 @@
+use crate::color::Color;
+use crate::vec3::Vec3;
+use std::sync::OnceLock;
+
+/// Visible range sampled by the spectral mode, in nanometres.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+/// Maps a uniform random number in [0, 1) to a wavelength.
+#[inline]
+pub fn sample_wavelength(u: f64) -> f64 {
+    LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+#[inline]
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = crate::clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Upsamples an RGB value to its spectrum and evaluates it at `lambda`.
+///
+/// The red, green and blue basis spectra are smooth steps that sum to one at
+/// every wavelength, so white stays exactly white and any RGB reflectance in
+/// [0, 1] gives a spectral reflectance in [0, 1].
+#[inline]
+pub fn rgb_to_spectrum(c: Color, lambda: f64) -> f64 {
+    let blue = 1.0 - smoothstep(480.0, 510.0, lambda);
+    let red = smoothstep(570.0, 600.0, lambda);
+    let green = 1.0 - red - blue;
+    c.0 * red + c.1 * green + c.2 * blue
+}
+
+#[inline]
+fn piecewise_gaussian(x: f64, mu: f64, sigma_lo: f64, sigma_hi: f64) -> f64 {
+    let sigma = if x < mu { sigma_lo } else { sigma_hi };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 colour matching functions, using the multi-lobe fit of
+/// Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu, lo, hi| piecewise_gaussian(lambda, mu, lo, hi);
+    Vec3(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// CIE XYZ to linear sRGB (D65).
+#[inline]
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Color {
+    Vec3(
+        3.2404542 * xyz.0 - 1.5371385 * xyz.1 - 0.4985314 * xyz.2,
+        -0.9692660 * xyz.0 + 1.8760108 * xyz.1 + 0.0415560 * xyz.2,
+        0.0556434 * xyz.0 - 0.2040259 * xyz.1 + 1.0572252 * xyz.2,
+    )
+}
+
+/// Integral of the Y matching function over the sampled range, and the
+/// linear sRGB of the equal-energy spectrum used to white balance the film.
+fn normalization() -> &'static (f64, Color) {
+    static NORMALIZATION: OnceLock<(f64, Color)> = OnceLock::new();
+    NORMALIZATION.get_or_init(|| {
+        let mut sum = Vec3(0.0, 0.0, 0.0);
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            sum += cie_xyz(lambda);
+            lambda += 1.0;
+        }
+        (sum.1, xyz_to_linear_srgb(sum / sum.1))
+    })
+}
+
+/// Converts the radiance carried by a single wavelength sample (drawn with
+/// `sample_wavelength`) into its contribution to a linear sRGB pixel.
+pub fn wavelength_to_rgb(radiance: f64, lambda: f64) -> Color {
+    let (y_integral, white) = *normalization();
+    let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+    let rgb = xyz_to_linear_srgb(cie_xyz(lambda) * (radiance / (pdf * y_integral)));
+    Vec3(rgb.0 / white.0, rgb.1 / white.1, rgb.2 / white.2)
+}
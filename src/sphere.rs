@@ -1,4 +1,7 @@
 use crate::{
+    aabb::Aabb,
+    camera::PI,
+    clamp,
     hittable::{HitRecord, Hittable},
     material::Material,
     ray::Ray,
@@ -7,24 +10,59 @@ use crate::{
 use std::rc::Rc;
 
 pub struct Sphere {
+    /// Center at time zero.
     pub center: Point3,
+    /// Distance the center moves per second.
+    pub velocity: Vec3,
     pub radius: f64,
     pub mat_ptr: Rc<dyn Material>,
 }
 
 impl Sphere {
     pub fn new(center: Point3, radius: f64, m: &Rc<dyn Material>) -> Self {
+        Sphere::moving(center, Vec3(0.0, 0.0, 0.0), radius, m)
+    }
+
+    /// A sphere moving in a straight line, blurred over the shutter interval.
+    pub fn moving(center: Point3, velocity: Vec3, radius: f64, m: &Rc<dyn Material>) -> Self {
         Sphere {
             center,
+            velocity,
             radius,
             mat_ptr: m.clone(),
         }
     }
+
+    #[inline]
+    pub fn center_at(&self, time: f64) -> Point3 {
+        self.center + time * self.velocity
+    }
+
+    /// Fills in u, v and the tangent frame for the point `q` relative to the
+    /// center. u goes around the Y axis starting at -X, v from -Y to +Y.
+    pub fn set_surface_coords(rec: &mut HitRecord, q: Vec3) {
+        let r = q.length();
+        let theta = clamp(-q.y() / r, -1.0, 1.0).acos();
+        let phi = f64::atan2(-q.z(), q.x()) + PI;
+        rec.u = phi / (2.0 * PI);
+        rec.v = theta / PI;
+
+        // Clamped so the frame stays well defined at the poles.
+        let sin_theta = theta.sin().max(1e-6);
+        rec.dpdu = 2.0 * PI * Vec3(q.z(), 0.0, -q.x());
+        rec.dpdv = PI
+            * Vec3(
+                -q.y() * q.x() / (r * sin_theta),
+                r * sin_theta,
+                -q.y() * q.z() / (r * sin_theta),
+            );
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc: Vec3 = r.orig() - self.center;
+        let center = self.center_at(r.time);
+        let oc: Vec3 = r.orig() - center;
         let a = r.dir().length_squared();
         let half_b = oc.dot(&r.dir());
         let c = oc.length_squared() - self.radius * self.radius;
@@ -44,11 +82,21 @@ impl Hittable for Sphere {
         let mut rec: HitRecord = HitRecord::default();
         rec.t = root;
         rec.p = r.at(rec.t);
-        rec.set_normal_face(r, (rec.p - self.center) / self.radius);
+        let q = rec.p - center;
+        rec.set_normal_face(r, q / self.radius);
+        Sphere::set_surface_coords(&mut rec, q);
         rec.mat_ptr = Some(self.mat_ptr.clone());
 
         Some(rec)
     }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        // Hollow spheres have a negative radius.
+        let r = self.radius.abs();
+        let extent = Vec3(r, r, r);
+        let start = Aabb::around(self.center_at(time0), extent);
+        Some(start.surrounding(&Aabb::around(self.center_at(time1), extent)))
+    }
 }
 
 impl std::convert::From<Sphere> for Rc<dyn Hittable> {
@@ -0,0 +1,148 @@
+use crate::camera::*;
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::str::FromStr;
+
+/// How the two eyes are packed into one image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StereoLayout {
+    /// Left eye on the left half.
+    SideBySide,
+    /// Left eye on the top half.
+    TopBottom,
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sbs" | "side-by-side" => Ok(StereoLayout::SideBySide),
+            "tb" | "top-bottom" => Ok(StereoLayout::TopBottom),
+            _ => Err(format!("Unknown stereo layout: {}", s)),
+        }
+    }
+}
+
+impl StereoLayout {
+    /// Aspect ratio of one eye's view inside a `width` x `height` image.
+    pub fn eye_aspect_ratio(self, width: i32, height: i32) -> f64 {
+        match self {
+            StereoLayout::SideBySide => (width as f64 / 2.0) / height as f64,
+            StereoLayout::TopBottom => width as f64 / (height as f64 / 2.0),
+        }
+    }
+}
+
+/// How the eyes' views meet at the convergence distance.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Convergence {
+    /// Both eyes rotate inwards to look at the convergence point.
+    ToeIn,
+    /// Both eyes keep looking straight ahead, with their frustums sheared to
+    /// overlap at the convergence distance. Free of the vertical parallax
+    /// toe-in causes.
+    OffAxis,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StereoRig {
+    pub layout: StereoLayout,
+    /// Distance between the eyes, in scene units.
+    pub interocular: f64,
+    /// Distance at which the eyes' views meet, the focus distance when not set.
+    pub convergence_dist: Option<f64>,
+    pub convergence: Convergence,
+}
+
+/// Renders a left and right eye view into one image, see `StereoLayout`.
+///
+/// Perspective eyes are shifted along the camera's right axis, toed in or
+/// sheared off-axis. Orthographic and fisheye eyes are only shifted (or
+/// toed in). Equirectangular panoramas use omnidirectional stereo.
+pub struct StereoCamera {
+    left: Box<dyn Camera>,
+    right: Box<dyn Camera>,
+    center: Box<dyn Camera>,
+    layout: StereoLayout,
+}
+
+impl StereoCamera {
+    pub fn new(
+        params: &CameraParams,
+        projection: Projection,
+        eye_aspect_ratio: f64,
+        rig: &StereoRig,
+    ) -> Self {
+        let half = rig.interocular / 2.0;
+        let convergence = rig.convergence_dist.unwrap_or(params.focus_dist);
+        let frame = CameraFrame::new(params.lookfrom, params.lookat, params.vup);
+        let center = params.build(projection, eye_aspect_ratio);
+
+        let eye = |shift: f64| -> Box<dyn Camera> {
+            let offset = shift * frame.u;
+            match (projection, rig.convergence) {
+                (Projection::Equirectangular, _) => {
+                    Box::new(EquirectangularCamera::omnidirectional_eye(
+                        params.lookfrom,
+                        params.lookat,
+                        params.vup,
+                        shift,
+                    ))
+                }
+                (Projection::Perspective, Convergence::OffAxis) => {
+                    let mono = PerspectiveCamera::new(
+                        params.lookfrom,
+                        params.lookat,
+                        params.vup,
+                        params.vfov,
+                        eye_aspect_ratio,
+                        params.aperture,
+                        params.focus_dist,
+                    )
+                    .with_aperture(params.aperture_shape.clone());
+                    Box::new(mono.shifted_eye(shift, convergence))
+                }
+                (_, Convergence::ToeIn) => {
+                    let eye_params = CameraParams {
+                        lookfrom: params.lookfrom + offset,
+                        lookat: params.lookfrom - convergence * frame.w,
+                        ..params.clone()
+                    };
+                    eye_params.build(projection, eye_aspect_ratio)
+                }
+                (_, Convergence::OffAxis) => {
+                    let eye_params = CameraParams {
+                        lookfrom: params.lookfrom + offset,
+                        lookat: params.lookat + offset,
+                        ..params.clone()
+                    };
+                    eye_params.build(projection, eye_aspect_ratio)
+                }
+            }
+        };
+
+        StereoCamera {
+            left: eye(-half),
+            right: eye(half),
+            center,
+            layout: rig.layout,
+        }
+    }
+}
+
+impl Camera for StereoCamera {
+    fn generate_ray(&self, s: f64, t: f64, lens: (f64, f64)) -> Option<Ray> {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => self.left.generate_ray(2.0 * s, t, lens),
+            StereoLayout::SideBySide => self.right.generate_ray(2.0 * s - 1.0, t, lens),
+            StereoLayout::TopBottom if t >= 0.5 => self.left.generate_ray(s, 2.0 * t - 1.0, lens),
+            StereoLayout::TopBottom => self.right.generate_ray(s, 2.0 * t, lens),
+        }
+    }
+
+    /// Depth as seen from between the eyes.
+    fn depth(&self, p: Point3) -> f64 {
+        self.center.depth(p)
+    }
+}
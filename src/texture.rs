@@ -0,0 +1,171 @@
+use crate::color::Color;
+use crate::vec3::*;
+use std::io::{self, Read};
+use std::path::Path;
+use std::rc::Rc;
+
+/// A color that varies over a surface, looked up by its (u, v) coordinates.
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+/* ========================================== */
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+/* ========================================== */
+/// A texture backed by an image, sampled with nearest-neighbour lookups.
+///
+/// Pixel values are kept linear in [0, 1], which is what normal, bump and
+/// mask maps expect.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    data: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, data: Vec<Color>) -> Self {
+        assert_eq!(
+            width * height,
+            data.len(),
+            "Image size does not match its data"
+        );
+        ImageTexture {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Width and height in pixels.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Loads an ASCII (P3) or binary (P6) PPM file.
+    pub fn load_ppm(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        std::fs::File::open(path)?.read_to_end(&mut bytes)?;
+        ImageTexture::parse_ppm(&bytes)
+    }
+
+    pub fn parse_ppm(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut pos = 0;
+
+        // Reads the next whitespace separated header token, skipping comments.
+        let mut token = || -> Option<String> {
+            loop {
+                while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                    pos += 1;
+                }
+                if pos < bytes.len() && bytes[pos] == b'#' {
+                    while pos < bytes.len() && bytes[pos] != b'\n' {
+                        pos += 1;
+                    }
+                } else {
+                    break;
+                }
+            }
+            let start = pos;
+            while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                None
+            } else {
+                Some(String::from_utf8_lossy(&bytes[start..pos]).into_owned())
+            }
+        };
+        let number = |token: Option<String>| -> io::Result<usize> {
+            token
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(|| invalid("Malformed PPM header"))
+        };
+
+        let magic = token().ok_or_else(|| invalid("Empty PPM file"))?;
+        let width = number(token())?;
+        let height = number(token())?;
+        let max_val = number(token())? as f64;
+        if width == 0 || height == 0 {
+            return Err(invalid("PPM image has no pixels"));
+        }
+        if max_val == 0.0 || max_val > 65535.0 {
+            return Err(invalid("PPM maximum value out of range"));
+        }
+        let count = width * height * 3;
+
+        let samples: Vec<f64> = match magic.as_str() {
+            "P3" => (0..count)
+                .map(|_| number(token()).map(|x| x as f64))
+                .collect::<io::Result<_>>()?,
+            "P6" => {
+                // A single whitespace byte separates the header from the raster.
+                let start = pos + 1;
+                let wide = max_val > 255.0;
+                let stride = if wide { 2 } else { 1 };
+                if bytes.len() < start + count * stride {
+                    return Err(invalid("Truncated PPM raster"));
+                }
+                (0..count)
+                    .map(|i| {
+                        let at = start + i * stride;
+                        if wide {
+                            (bytes[at] as usize * 256 + bytes[at + 1] as usize) as f64
+                        } else {
+                            bytes[at] as f64
+                        }
+                    })
+                    .collect()
+            }
+            _ => return Err(invalid("Unsupported PPM format")),
+        };
+
+        let data = samples
+            .chunks(3)
+            .map(|c| Vec3(c[0], c[1], c[2]) / max_val)
+            .collect();
+        Ok(ImageTexture::new(width, height, data))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // Wrap around, with v = 0 at the bottom of the image.
+        let u = u - u.floor();
+        let v = 1.0 - (v - v.floor());
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.data[j * self.width + i]
+    }
+}
+
+impl From<SolidColor> for Rc<dyn Texture> {
+    #[inline]
+    fn from(texture: SolidColor) -> Self {
+        let trait_object: Rc<dyn Texture> = Rc::new(texture);
+        trait_object
+    }
+}
+
+impl From<ImageTexture> for Rc<dyn Texture> {
+    #[inline]
+    fn from(texture: ImageTexture) -> Self {
+        let trait_object: Rc<dyn Texture> = Rc::new(texture);
+        trait_object
+    }
+}
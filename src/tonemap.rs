@@ -0,0 +1,160 @@
+use crate::color::Color;
+use crate::film::luminance;
+use crate::vec3::Vec3;
+use std::str::FromStr;
+
+/// Operator compressing scene referred HDR values into [0, 1].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMap {
+    /// Values above one are clipped.
+    Clamp,
+    Reinhard,
+    /// Reinhard with a white point, the luminance mapped to exactly one.
+    ExtendedReinhard,
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+    /// Troy Sobotka's AgX, in its commonly used polynomial approximation.
+    AgX,
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" | "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "reinhard-extended" => Ok(ToneMap::ExtendedReinhard),
+            "aces" => Ok(ToneMap::Aces),
+            "agx" => Ok(ToneMap::AgX),
+            _ => Err(format!("Unknown tone map: {}", s)),
+        }
+    }
+}
+
+/// Turns the linear HDR pixels of the film into display values: exposure,
+/// then tone mapping, then the output transfer function.
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayTransform {
+    /// Exposure compensation in stops.
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    /// Luminance mapped to white by `ToneMap::ExtendedReinhard`.
+    pub white_point: f64,
+    /// Encode with a pure power curve instead of the sRGB OETF.
+    pub gamma: Option<f64>,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        DisplayTransform {
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            white_point: 4.0,
+            gamma: None,
+        }
+    }
+}
+
+/// Multiplies `v` by the 3x3 matrix given as rows.
+#[inline]
+fn mul(m: &[[f64; 3]; 3], v: Color) -> Color {
+    Vec3(
+        m[0][0] * v.0 + m[0][1] * v.1 + m[0][2] * v.2,
+        m[1][0] * v.0 + m[1][1] * v.1 + m[1][2] * v.2,
+        m[2][0] * v.0 + m[2][1] * v.1 + m[2][2] * v.2,
+    )
+}
+
+#[inline]
+fn map(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    Vec3(f(c.0), f(c.1), f(c.2))
+}
+
+/// Scales `c` so its luminance becomes `f(luminance)`, keeping its hue.
+#[inline]
+fn map_luminance(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    let l = luminance(c);
+    if l <= 0.0 {
+        Vec3(0.0, 0.0, 0.0)
+    } else {
+        c * (f(l) / l)
+    }
+}
+
+fn aces(c: Color) -> Color {
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let rrt_and_odt = |v: f64| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    };
+    mul(&OUTPUT, map(mul(&INPUT, c), rrt_and_odt))
+}
+
+fn agx(c: Color) -> Color {
+    const INSET: [[f64; 3]; 3] = [
+        [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+        [0.0423756549057051, 0.0784336, 0.879142973793104],
+    ];
+    const OUTSET: [[f64; 3]; 3] = [
+        [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+    ];
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    let contrast = |v: f64| {
+        // Log2 encoding of the inset color, then the sigmoid fit.
+        let x = crate::clamp((v.max(1e-10).log2() - MIN_EV) / (MAX_EV - MIN_EV), 0.0, 1.0);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+    // The curve outputs display encoded values, linearized again for the OETF.
+    let display = mul(&OUTSET, map(mul(&INSET, c), contrast));
+    map(display, |v| v.max(0.0).powf(2.2))
+}
+
+#[inline]
+fn srgb_oetf(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl DisplayTransform {
+    /// Maps a linear HDR color to display encoded values in [0, 1].
+    pub fn apply(&self, c: Color) -> Color {
+        let c = map(c * 2f64.powf(self.exposure), |x| x.max(0.0));
+        let white2 = self.white_point * self.white_point;
+        let mapped = match self.tone_map {
+            ToneMap::Clamp => c,
+            ToneMap::Reinhard => map_luminance(c, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard => map_luminance(c, |l| l * (1.0 + l / white2) / (1.0 + l)),
+            ToneMap::Aces => aces(c),
+            ToneMap::AgX => agx(c),
+        };
+        map(mapped, |x| {
+            let x = crate::clamp(x, 0.0, 1.0);
+            match self.gamma {
+                Some(gamma) => x.powf(1.0 / gamma),
+                None => srgb_oetf(x),
+            }
+        })
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{
+    aabb::Aabb,
+    camera::PI,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    roots::{polish, solve_quartic},
+    vec3::*,
+};
+use std::rc::Rc;
+
+/// A torus lying flat, its ring going around the Y axis.
+pub struct Torus {
+    pub center: Point3,
+    /// Distance from the center to the middle of the tube.
+    pub major_radius: f64,
+    /// Radius of the tube.
+    pub minor_radius: f64,
+    pub mat_ptr: Rc<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(center: Point3, major_radius: f64, minor_radius: f64, m: &Rc<dyn Material>) -> Self {
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+            mat_ptr: m.clone(),
+        }
+    }
+
+    fn bounds(&self) -> Aabb {
+        let outer = self.major_radius + self.minor_radius;
+        Aabb::around(self.center, Vec3(outer, self.minor_radius, outer))
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bounds().hit(r, t_min, t_max)?;
+
+        // The quartic is solved along the unit direction, from the point of
+        // the ray closest to the center, which keeps its coefficients small
+        // and the roots precise.
+        let length = r.dir().length();
+        let d = r.dir() / length;
+        let start = -(r.orig() - self.center).dot(&d);
+        let o = r.orig() - self.center + start * d;
+
+        // (|p|² + R² - r²)² = 4R² (x² + z²) for p = o + s d.
+        let (big, small) = (self.major_radius, self.minor_radius);
+        let e = o.length_squared() - big * big - small * small;
+        let od = o.dot(&d);
+        let c = [
+            e * e - 4.0 * big * big * (small * small - o.y() * o.y()),
+            4.0 * od * e + 8.0 * big * big * o.y() * d.y(),
+            2.0 * e + 4.0 * od * od + 4.0 * big * big * d.y() * d.y(),
+            4.0 * od,
+            1.0,
+        ];
+        let root = solve_quartic(c)
+            .into_iter()
+            .map(|s| (start + polish(&c, s)) / length)
+            .filter(|&t| t_min <= t && t <= t_max)
+            .fold(None, |nearest: Option<f64>, t| {
+                Some(nearest.map_or(t, |n| n.min(t)))
+            })?;
+
+        let mut rec = HitRecord {
+            t: root,
+            p: r.at(root),
+            ..Default::default()
+        };
+        let q = rec.p - self.center;
+        let rho = (q.x() * q.x() + q.z() * q.z()).sqrt().max(1e-12);
+        let radial = Vec3(q.x(), 0.0, q.z()) / rho;
+        rec.set_normal_face(r, (q - big * radial) / small);
+        // u goes around the Y axis like on a sphere, v around the tube
+        // starting on its inner side.
+        rec.u = (f64::atan2(-q.z(), q.x()) + PI) / (2.0 * PI);
+        rec.v = (f64::atan2(q.y(), rho - big) + PI) / (2.0 * PI);
+        rec.dpdu = 2.0 * PI * Vec3(q.z(), 0.0, -q.x());
+        rec.dpdv = 2.0 * PI * (-q.y() * radial + Vec3(0.0, rho - big, 0.0));
+        rec.mat_ptr = Some(self.mat_ptr.clone());
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bounds())
+    }
+}
+
+impl std::convert::From<Torus> for Rc<dyn Hittable> {
+    fn from(torus: Torus) -> Self {
+        let trait_object: Rc<dyn Hittable> = Rc::new(torus);
+        trait_object
+    }
+}
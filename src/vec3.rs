@@ -7,7 +7,7 @@ pub type Point3 = Vec3;
 /// Returns a random real in [0.1)
 #[inline]
 pub fn random_f64() -> f64 {
-    rand::random::<f64>()
+    crate::rng::next_f64()
 }
 #[inline]
 pub fn random_range(min: f64, max: f64) -> f64 {
@@ -63,6 +63,30 @@ impl Vec3 {
         }
     }
 
+    /// Maps a point of the unit square onto the unit disk, keeping strata
+    /// intact (Shirley and Chiu's concentric mapping).
+    pub fn concentric_disk(u: (f64, f64)) -> Self {
+        let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3(0.0, 0.0, 0.0);
+        }
+        let quarter_pi = std::f64::consts::FRAC_PI_4;
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, quarter_pi * (b / a))
+        } else {
+            (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+        };
+        Vec3(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
+    /// Maps a point of the unit square to a uniformly distributed unit vector.
+    pub fn unit_vector_from_sample(u: (f64, f64)) -> Self {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * u.1;
+        Vec3(r * phi.cos(), r * phi.sin(), z)
+    }
+
     #[inline]
     pub fn random_in_hemishpere() -> Self {
         Vec3::random()